@@ -0,0 +1,445 @@
+//! A compact binary cache of a loaded hierarchy, to restore a point cloud without
+//! fetching and parsing its hierarchy chunks again.
+//!
+//! The cache holds the metadata as json, where the hierarchy and the points are stored,
+//! the size and version of the hierarchy when the cache was written, and the loaded nodes
+//! breadth first, in the same spirit as the `hierarchy.bin` entries.
+
+use crate::octree::node::OctreeNode;
+use crate::octree::{FlatOctree, NodeId};
+use crate::point_cloud::ReadHierarchyError;
+use crate::resource::ResourceInfo;
+use binrw::prelude::*;
+use std::collections::VecDeque;
+
+/// Version of the cache format, caches written with another version are rejected.
+pub const CACHE_VERSION: u32 = 1;
+
+#[binrw]
+#[derive(Clone, Debug)]
+#[brw(little, magic = b"PTRCACHE")]
+pub(crate) struct HierarchyCache {
+    pub version: u32,
+    pub source: CachedResourceInfo,
+    pub layout: CachedLayout,
+    /// The metadata, serialized as json
+    pub metadata: CachedString,
+    #[bw(calc = nodes.len() as u32)]
+    num_nodes: u32,
+    #[br(count = num_nodes)]
+    pub nodes: Vec<CachedNode>,
+}
+
+#[binrw]
+#[derive(Clone, Debug, Default)]
+#[brw(little)]
+pub(crate) struct CachedString {
+    #[bw(calc = bytes.len() as u32)]
+    len: u32,
+    #[br(count = len)]
+    pub bytes: Vec<u8>,
+}
+
+#[binrw]
+#[derive(Clone, Debug)]
+#[brw(little)]
+pub(crate) struct CachedResourceInfo {
+    // 0 if the size is unknown
+    has_size: u8,
+    size: u64,
+    // empty if the version is unknown
+    etag: CachedString,
+}
+
+#[binrw]
+#[derive(Clone, Debug)]
+#[brw(little)]
+pub(crate) enum CachedLayout {
+    #[brw(magic = 0u8)]
+    Potree2 {
+        hierarchy_url: CachedString,
+        octree_url: CachedString,
+    },
+    #[brw(magic = 1u8)]
+    Legacy {
+        octree_url: CachedString,
+        hierarchy_step_size: u16,
+        extension: CachedString,
+    },
+}
+
+/// A loaded node. Its name, bounding box, spacing and level are computed from its parent.
+#[binrw]
+#[derive(Clone, Debug)]
+#[brw(little)]
+pub(crate) struct CachedNode {
+    pub node_type: u8,
+    /// Children stored in the cache, which may differ from the children in the hierarchy
+    /// if the node is a proxy.
+    pub child_mask: u8,
+    pub num_points: u32,
+    pub byte_offset: u64,
+    pub byte_size: u64,
+    pub hierarchy_byte_offset: u64,
+    pub hierarchy_byte_size: u64,
+}
+
+impl From<&str> for CachedString {
+    fn from(value: &str) -> Self {
+        Self {
+            bytes: value.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl CachedString {
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).into_owned()
+    }
+}
+
+impl From<&ResourceInfo> for CachedResourceInfo {
+    fn from(info: &ResourceInfo) -> Self {
+        Self {
+            has_size: info.size.is_some() as u8,
+            size: info.size.unwrap_or_default(),
+            etag: info.etag.as_deref().unwrap_or_default().into(),
+        }
+    }
+}
+
+impl From<&CachedResourceInfo> for ResourceInfo {
+    fn from(info: &CachedResourceInfo) -> Self {
+        Self {
+            size: (info.has_size != 0).then_some(info.size),
+            etag: (!info.etag.bytes.is_empty()).then(|| info.etag.to_string_lossy()),
+        }
+    }
+}
+
+/// Returns true if `current` is known to be the same version of the resource as `cached`.
+///
+/// The `ETag`s are compared if both are known, the sizes otherwise.
+pub(crate) fn is_up_to_date(cached: &ResourceInfo, current: &ResourceInfo) -> bool {
+    match (&cached.etag, &current.etag) {
+        (Some(cached_etag), Some(current_etag)) => cached_etag == current_etag,
+        _ => cached.size.is_some() && cached.size == current.size,
+    }
+}
+
+/// Collects the loaded nodes breadth first, children in order.
+pub(crate) fn cache_nodes(octree: &FlatOctree<OctreeNode>) -> Vec<CachedNode> {
+    octree
+        .breadth_first(octree.root_id())
+        .map(|(_, node)| {
+            let child_mask = node
+                .children
+                .iter()
+                .filter_map(|child_id| octree.node(*child_id))
+                .filter_map(|child| child.key()?.child_index())
+                .fold(0_u8, |mask, index| mask | (1 << index));
+
+            CachedNode {
+                node_type: node.node_type,
+                child_mask,
+                num_points: node.num_points,
+                byte_offset: node.byte_offset,
+                byte_size: node.byte_size,
+                hierarchy_byte_offset: node.hierarchy_byte_offset,
+                hierarchy_byte_size: node.hierarchy_byte_size,
+            }
+        })
+        .collect()
+}
+
+/// Rebuilds the loaded nodes below the root of `octree`, which must have no children.
+pub(crate) fn restore_nodes(
+    octree: &mut FlatOctree<OctreeNode>,
+    nodes: Vec<CachedNode>,
+) -> Result<(), ReadHierarchyError> {
+    let root_name = octree.root().name.clone();
+
+    // check that the child masks describe exactly the cached nodes
+    let mut num_nodes = 1;
+    for (i, node) in nodes.iter().enumerate() {
+        if i >= num_nodes {
+            break;
+        }
+
+        num_nodes += node.child_mask.count_ones() as usize;
+        if num_nodes > nodes.len() {
+            return Err(ReadHierarchyError::InvalidChildMask {
+                node: root_name,
+                entry: i,
+                required: num_nodes,
+                available: nodes.len(),
+            });
+        }
+    }
+    if num_nodes != nodes.len() {
+        return Err(ReadHierarchyError::InvalidHierarchySize {
+            node: root_name,
+            offset: 0,
+            size: nodes.len(),
+        });
+    }
+
+    octree.reserve(nodes.len());
+    let mut queue = VecDeque::from([octree.root_id()]);
+
+    for cached in nodes {
+        let current_id: NodeId = queue.pop_front().expect("nodes checked above");
+        let current = octree.node_mut(current_id).unwrap();
+
+        current.node_type = cached.node_type;
+        current.num_points = cached.num_points;
+        current.byte_offset = cached.byte_offset;
+        current.byte_size = cached.byte_size;
+        current.hierarchy_byte_offset = cached.hierarchy_byte_offset;
+        current.hierarchy_byte_size = cached.hierarchy_byte_size;
+
+        // clone/copy just what we need
+        let (current_name, current_bounding_box, current_spacing, current_level) = (
+            current.name.clone(),
+            current.bounding_box.clone(),
+            current.spacing,
+            current.level,
+        );
+
+        let mut children = Vec::with_capacity(cached.child_mask.count_ones() as usize);
+
+        for child_index in 0..8 {
+            let child_exists = ((1 << child_index) & cached.child_mask) != 0;
+            if !child_exists {
+                continue;
+            }
+
+            let child_id = octree.insert(OctreeNode {
+                name: format!("{}{}", current_name, child_index),
+                bounding_box: current_bounding_box.child(child_index),
+                spacing: current_spacing / 2.0,
+                level: current_level + 1,
+                parent: Some(current_id),
+                ..Default::default()
+            });
+            octree.node_mut(child_id).unwrap().id = Some(child_id);
+
+            children.push(child_id);
+            queue.push_back(child_id);
+        }
+
+        octree.node_mut(current_id).unwrap().children = children;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{
+        AttributeMetadata, AttributeType, BoundingBox, HierarchyMetadata, Metadata,
+    };
+    use crate::point_cloud::{CacheError, PotreePointCloud};
+    use crate::resource::ResourceLoader;
+    use std::io::Cursor;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            version: "2.0".to_string(),
+            name: "cache".to_string(),
+            description: String::new(),
+            points: 0,
+            projection: String::new(),
+            hierarchy: HierarchyMetadata {
+                first_chunk_size: 0,
+                // smaller than the depth, so some nodes are written as proxies
+                step_size: 2,
+                depth: 0,
+            },
+            offset: [0.0; 3],
+            scale: [0.001; 3],
+            spacing: 1.0,
+            bounding_box: BoundingBox {
+                min: [0.0; 3],
+                max: [8.0; 3],
+            },
+            encoding: "DEFAULT".to_string(),
+            attributes: vec![AttributeMetadata::new("position", AttributeType::Int32, 3)],
+        }
+    }
+
+    // A cache holding the root only
+    fn cache_bytes(version: u32) -> Vec<u8> {
+        let cache = HierarchyCache {
+            version,
+            source: (&ResourceInfo::default()).into(),
+            layout: CachedLayout::Potree2 {
+                hierarchy_url: "hierarchy.bin".into(),
+                octree_url: "octree.bin".into(),
+            },
+            metadata: CachedString {
+                bytes: serde_json::to_vec(&metadata()).unwrap(),
+            },
+            nodes: vec![CachedNode {
+                node_type: 1,
+                child_mask: 0,
+                num_points: 0,
+                byte_offset: 0,
+                byte_size: 0,
+                hierarchy_byte_offset: 0,
+                hierarchy_byte_size: 0,
+            }],
+        };
+
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.write_le(&cache).unwrap();
+        cursor.into_inner()
+    }
+
+    fn from_cache(bytes: &[u8]) -> Result<PotreePointCloud, CacheError> {
+        futures::executor::block_on(PotreePointCloud::from_cache(bytes, ResourceLoader::new()))
+    }
+
+    #[test]
+    fn from_cache_rejects_invalid_magic() {
+        let mut bytes = cache_bytes(CACHE_VERSION);
+        bytes[0] = b'X';
+
+        assert!(matches!(
+            from_cache(&bytes),
+            Err(CacheError::InvalidBinaryData(_))
+        ));
+    }
+
+    #[test]
+    fn from_cache_rejects_other_versions() {
+        let bytes = cache_bytes(CACHE_VERSION + 1);
+
+        assert!(matches!(
+            from_cache(&bytes),
+            Err(CacheError::UnsupportedVersion(version)) if version == CACHE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn from_cache_rejects_truncated_cache() {
+        let bytes = cache_bytes(CACHE_VERSION);
+
+        for len in [4, 20, bytes.len() - 1] {
+            assert!(
+                matches!(
+                    from_cache(&bytes[..len]),
+                    Err(CacheError::InvalidBinaryData(_))
+                ),
+                "{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn is_up_to_date_compares_etags_then_sizes() {
+        let info = |size: Option<u64>, etag: Option<&str>| ResourceInfo {
+            size,
+            etag: etag.map(str::to_string),
+        };
+
+        // the etags win over the sizes
+        assert!(is_up_to_date(
+            &info(Some(10), Some("a")),
+            &info(Some(20), Some("a"))
+        ));
+        assert!(!is_up_to_date(
+            &info(Some(10), Some("a")),
+            &info(Some(10), Some("b"))
+        ));
+
+        // sizes only, if an etag is missing
+        assert!(is_up_to_date(
+            &info(Some(10), Some("a")),
+            &info(Some(10), None)
+        ));
+        assert!(is_up_to_date(&info(Some(10), None), &info(Some(10), None)));
+        assert!(!is_up_to_date(&info(Some(10), None), &info(Some(20), None)));
+        assert!(!is_up_to_date(&info(None, None), &info(None, None)));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn cache_round_trip() {
+        use crate::octree::aabb::Aabb;
+        use crate::octree::key::OctreeKey;
+        use crate::point::buffer::PointBuffer;
+        use crate::writer::PotreeWriter;
+
+        let metadata = metadata();
+        let root = Aabb::from(metadata.bounding_box.clone());
+        let mut writer = PotreeWriter::new(metadata);
+        for name in ["r", "r0", "r03", "r036", "r0361", "r7", "r70", "r701"] {
+            let bounding_box = OctreeKey::from_name(name).unwrap().bounding_box(&root);
+            let mut points = PointBuffer::new(1);
+            points.positions.push(bounding_box.center());
+            writer.add_node(name, points).unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("potree-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        writer.write().unwrap().write_to_dir(&dir).unwrap();
+
+        futures::executor::block_on(async {
+            let url = dir.to_str().unwrap();
+            let point_cloud = PotreePointCloud::from_url(url, ResourceLoader::new())
+                .await
+                .unwrap();
+            let cache = point_cloud.to_cache().await.unwrap();
+
+            let mut restored = PotreePointCloud::from_cache(&cache, ResourceLoader::new())
+                .await
+                .unwrap();
+
+            let octree = point_cloud.octree();
+            assert_eq!(restored.octree().len(), octree.len());
+            for (_, node) in octree.breadth_first(octree.root_id()) {
+                let restored_id = restored.node_id_by_name(&node.name).unwrap();
+                let restored_node = restored.octree().node(restored_id).unwrap();
+                let child_names = |point_cloud: &PotreePointCloud, children: &[NodeId]| {
+                    children
+                        .iter()
+                        .map(|child| point_cloud.octree().node(*child).unwrap().name.clone())
+                        .collect::<Vec<_>>()
+                };
+
+                assert_eq!(restored_node.node_type, node.node_type, "{}", node.name);
+                assert_eq!(restored_node.num_points, node.num_points, "{}", node.name);
+                assert_eq!(restored_node.byte_offset, node.byte_offset, "{}", node.name);
+                assert_eq!(restored_node.byte_size, node.byte_size, "{}", node.name);
+                assert_eq!(
+                    restored_node.hierarchy_byte_offset, node.hierarchy_byte_offset,
+                    "{}",
+                    node.name
+                );
+                assert_eq!(
+                    restored_node.hierarchy_byte_size, node.hierarchy_byte_size,
+                    "{}",
+                    node.name
+                );
+                assert_eq!(
+                    child_names(&restored, &restored_node.children),
+                    child_names(&point_cloud, &node.children),
+                    "{}",
+                    node.name
+                );
+            }
+
+            // the proxies are still loadable
+            for proxy in ["r03", "r70"] {
+                let node_id = restored.node_id_by_name(proxy).unwrap();
+                assert_eq!(restored.octree().node(node_id).unwrap().node_type, 2);
+            }
+            restored.load_entire_hierarchy().await.unwrap();
+            assert_eq!(restored.octree().len(), 8);
+        });
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,620 @@
+//! Building a Potree 2.0 dataset from raw points, in the spirit of PotreeConverter.
+//!
+//! Points are first distributed in chunks, subtrees of the octree small enough to be indexed
+//! in memory. A chunk is split in eight when it holds too many points, and the chunks are
+//! spilled to temporary files when the points held in memory exceed the budget.
+//!
+//! Once every point has been added, each chunk is indexed: its nodes are split until they hold
+//! few enough points, then subsampled bottom-up, the points kept by a node being removed from
+//! its children. The levels above the chunks are finally subsampled the same way.
+
+use crate::decoder::decode_points;
+use crate::encoder::encode_points;
+use crate::metadata::{AttributeMetadata, AttributeType, BoundingBox, HierarchyMetadata, Metadata};
+use crate::octree::aabb::Aabb;
+use crate::octree::node::OctreeNode;
+use crate::point::buffer::{AppendError, AttributeSelection, PointBuffer, is_position_attribute};
+use crate::point_cloud::LoadPointsError;
+use crate::writer::{
+    AttributeRanges, WriteDatasetError, WrittenNode, encode_hierarchy, finish_metadata,
+};
+use glam::{DVec3, I64Vec3};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+// Nodes are never split below this level, e.g. if they hold many duplicated points
+const MAX_LEVEL: usize = 24;
+
+#[derive(Error, Debug)]
+pub enum ConvertError {
+    #[error("Point {0} is outside of the bounding box")]
+    PointOutsideBoundingBox(DVec3),
+
+    #[error("Invalid hierarchy step size {0}")]
+    InvalidStepSize(u16),
+
+    #[error("Error encoding points: {0}")]
+    WriteDatasetError(#[from] WriteDatasetError),
+
+    #[error("Error reading spilled points: {0}")]
+    LoadPointsError(#[from] LoadPointsError),
+
+    #[error("Error merging points: {0}")]
+    AppendError(#[from] AppendError),
+
+    #[error("Error writing dataset: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// How the points of a node are chosen among the points of its children.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Sampling {
+    /// Keep the points further than the node spacing from every kept point.
+    #[default]
+    Poisson,
+    /// Keep one point per cell of a grid whose cells are the size of the node spacing.
+    Grid,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConverterOptions {
+    pub name: String,
+    /// `BROTLI` or `DEFAULT`
+    pub encoding: String,
+    /// Precision of the stored coordinates
+    pub scale: DVec3,
+    /// Spacing of the root node, the size of the octree divided by 128 if `None`
+    pub spacing: Option<f64>,
+    pub sampling: Sampling,
+    /// Nodes holding more points are split
+    pub max_node_points: usize,
+    /// Chunks holding more points are split, this bounds the points indexed at once,
+    /// except for the chunks below level 24 which are never split, e.g. if they hold many
+    /// duplicated points
+    pub max_chunk_points: usize,
+    /// Chunks are spilled to temporary files when more points are held in memory
+    ///
+    /// This only bounds the points held while they are added. When finishing, the points of
+    /// one chunk are loaded at a time, along with the points kept by the roots of the chunks
+    /// already indexed, until the levels above the chunks are written.
+    pub max_points_in_memory: usize,
+    /// Levels of the hierarchy chunks of `hierarchy.bin`
+    pub step_size: u16,
+}
+
+impl Default for ConverterOptions {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            encoding: "BROTLI".to_string(),
+            scale: DVec3::splat(0.001),
+            spacing: None,
+            sampling: Sampling::default(),
+            max_node_points: 20_000,
+            max_chunk_points: 2_000_000,
+            max_points_in_memory: 10_000_000,
+            step_size: 4,
+        }
+    }
+}
+
+/// Builds a Potree 2.0 dataset in a directory from points added in batches.
+///
+/// Temporary files are written in a `.chunks` directory next to the dataset, emptied when
+/// the converter is created and removed once the dataset is written.
+pub struct PotreeConverter {
+    dir: PathBuf,
+    metadata: Metadata,
+    // points are held and spilled as rows of the DEFAULT encoding
+    rows_metadata: Metadata,
+    row_size: usize,
+    position_offset: usize,
+    options: ConverterOptions,
+    chunks: BTreeMap<String, Chunk>,
+    rows_in_memory: usize,
+}
+
+// Points of a leaf of the chunk tree
+#[derive(Default)]
+struct Chunk {
+    rows: Vec<u8>,
+    num_spilled: usize,
+}
+
+// Encoded nodes
+struct OctreeWriter {
+    file: BufWriter<File>,
+    byte_offset: u64,
+    nodes: BTreeMap<String, WrittenNode>,
+    ranges: AttributeRanges,
+}
+
+impl PotreeConverter {
+    /// Creates a converter writing in `dir`, for points inside the bounding box holding
+    /// the attributes. A `position` attribute is added to the attributes if missing.
+    ///
+    /// The octree is the smallest cube starting at the minimum of the bounding box and
+    /// holding it, the positions are stored relative to this minimum.
+    pub fn new<P: AsRef<Path>>(
+        dir: P,
+        bounding_box: &Aabb,
+        mut attributes: Vec<AttributeMetadata>,
+        options: ConverterOptions,
+    ) -> Result<Self, ConvertError> {
+        if options.step_size == 0 {
+            return Err(ConvertError::InvalidStepSize(options.step_size));
+        }
+
+        let dir = dir.as_ref().to_path_buf();
+        // chunks left by an interrupted conversion would be merged in this one
+        let chunks_dir = dir.join(".chunks");
+        if chunks_dir.exists() {
+            fs::remove_dir_all(&chunks_dir)?;
+        }
+        fs::create_dir_all(chunks_dir)?;
+
+        if !attributes
+            .iter()
+            .any(|attribute| is_position_attribute(&attribute.name))
+        {
+            attributes.insert(
+                0,
+                AttributeMetadata::new("position", AttributeType::Int32, 3),
+            );
+        }
+
+        let size = bounding_box.size().max_element();
+        let min = bounding_box.min;
+
+        let metadata = Metadata {
+            version: "2.0".to_string(),
+            name: options.name.clone(),
+            description: String::new(),
+            points: 0,
+            projection: String::new(),
+            hierarchy: HierarchyMetadata {
+                first_chunk_size: 0,
+                step_size: options.step_size,
+                depth: 0,
+            },
+            offset: min.to_array(),
+            scale: options.scale.to_array(),
+            spacing: options.spacing.unwrap_or(size / 128.0),
+            bounding_box: BoundingBox {
+                min: min.to_array(),
+                max: (min + DVec3::splat(size)).to_array(),
+            },
+            encoding: options.encoding.clone(),
+            attributes,
+        };
+
+        let rows_metadata = Metadata {
+            encoding: "DEFAULT".to_string(),
+            ..metadata.clone()
+        };
+        let row_size = metadata
+            .attributes
+            .iter()
+            .map(|attribute| attribute.size as usize)
+            .sum();
+        let position_offset = metadata
+            .attributes
+            .iter()
+            .take_while(|attribute| !is_position_attribute(&attribute.name))
+            .map(|attribute| attribute.size as usize)
+            .sum();
+
+        Ok(Self {
+            dir,
+            metadata,
+            rows_metadata,
+            row_size,
+            position_offset,
+            options,
+            chunks: BTreeMap::from([("r".to_string(), Chunk::default())]),
+            rows_in_memory: 0,
+        })
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Adds points, which must hold every attribute of the dataset.
+    pub fn add_points(&mut self, points: &PointBuffer) -> Result<(), ConvertError> {
+        let octree = self.octree_bounding_box();
+        if let Some(position) = points
+            .positions
+            .iter()
+            .find(|position| !octree.contains_point(**position))
+        {
+            return Err(ConvertError::PointOutsideBoundingBox(*position));
+        }
+
+        let rows = encode_points(&self.rows_metadata, "r", points)?;
+
+        let mut batches: HashMap<String, Vec<u8>> = HashMap::new();
+        for (position, row) in points
+            .positions
+            .iter()
+            .zip(rows.chunks_exact(self.row_size))
+        {
+            batches
+                .entry(self.chunk_of(*position))
+                .or_default()
+                .extend_from_slice(row);
+        }
+
+        for (name, rows) in batches {
+            self.rows_in_memory += rows.len() / self.row_size;
+            self.chunks
+                .get_mut(&name)
+                .expect("chunk found above, shouldn't happen")
+                .rows
+                .extend(rows);
+            self.split_chunk(&name)?;
+        }
+
+        if self.rows_in_memory > self.options.max_points_in_memory {
+            self.spill_chunks()?;
+        }
+
+        Ok(())
+    }
+
+    /// Indexes the points and writes `octree.bin`, `hierarchy.bin` and `metadata.json`.
+    pub fn finish(mut self) -> Result<Metadata, ConvertError> {
+        let mut octree = OctreeWriter {
+            file: BufWriter::new(File::create(self.dir.join("octree.bin"))?),
+            byte_offset: 0,
+            nodes: BTreeMap::new(),
+            ranges: AttributeRanges::new(&self.metadata.attributes),
+        };
+
+        // the points of each chunk root, once its subtree is written
+        let mut pending: BTreeMap<String, PointBuffer> = BTreeMap::new();
+        let names: Vec<String> = self.chunks.keys().cloned().collect();
+        for name in names {
+            let points = self.load_chunk(&name)?;
+            if points.num_points == 0 {
+                continue;
+            }
+
+            let indices = (0..points.num_points).collect();
+            let kept = self.index(&points, &name, indices, &mut octree)?;
+            pending.insert(name, points.select(&kept));
+        }
+
+        // levels above the chunks, deepest first
+        let max_level = pending.keys().map(|name| name.len() - 1).max().unwrap_or(0);
+        for level in (0..max_level).rev() {
+            let mut parents: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            for name in pending.keys().filter(|name| name.len() - 1 == level + 1) {
+                parents
+                    .entry(name[..name.len() - 1].to_string())
+                    .or_default()
+                    .push(name.clone());
+            }
+
+            for (parent, children) in parents {
+                let mut candidates = PointBuffer::default();
+                let mut ranges = Vec::with_capacity(children.len());
+                for child in &children {
+                    let points = pending.remove(child).expect("child listed above");
+                    ranges.push(candidates.num_points..candidates.num_points + points.num_points);
+                    candidates.append(points)?;
+                }
+
+                let kept = self.subsample(
+                    &candidates,
+                    (0..candidates.num_points).collect(),
+                    &self.node_bounding_box(&parent),
+                    level,
+                );
+                let mut is_kept = vec![false; candidates.num_points];
+                for i in &kept {
+                    is_kept[*i] = true;
+                }
+
+                for (child, range) in children.iter().zip(ranges) {
+                    let remaining: Vec<usize> = range.filter(|i| !is_kept[*i]).collect();
+                    octree.write_node(&self.metadata, child, &candidates.select(&remaining))?;
+                }
+                pending.insert(parent, candidates.select(&kept));
+            }
+        }
+
+        let root = pending.remove("r").unwrap_or_default();
+        octree.write_node(&self.metadata, "r", &root)?;
+        octree.file.flush()?;
+
+        let (hierarchy, first_chunk_size) =
+            encode_hierarchy(&octree.nodes, self.metadata.hierarchy.step_size)?;
+        fs::write(self.dir.join("hierarchy.bin"), hierarchy)?;
+
+        finish_metadata(&mut self.metadata, &octree.nodes, first_chunk_size);
+        octree.ranges.apply(&mut self.metadata.attributes);
+        fs::write(
+            self.dir.join("metadata.json"),
+            serde_json::to_string_pretty(&self.metadata).map_err(WriteDatasetError::from)?,
+        )?;
+
+        fs::remove_dir_all(self.dir.join(".chunks"))?;
+
+        Ok(self.metadata)
+    }
+
+    // Splits the node until its children hold few enough points, subsamples them, writes
+    // the children and returns the points kept by the node.
+    fn index(
+        &self,
+        points: &PointBuffer,
+        name: &str,
+        indices: Vec<usize>,
+        octree: &mut OctreeWriter,
+    ) -> Result<Vec<usize>, ConvertError> {
+        let level = name.len() - 1;
+        if indices.len() <= self.options.max_node_points || level >= MAX_LEVEL {
+            return Ok(indices);
+        }
+
+        let bounding_box = self.node_bounding_box(name);
+        let center = bounding_box.center();
+
+        let mut children: [Vec<usize>; 8] = Default::default();
+        for i in indices {
+            children[child_index(center, points.positions[i])].push(i);
+        }
+
+        let mut candidates = Vec::new();
+        let mut child_candidates = Vec::new();
+        for (index, child_indices) in children.into_iter().enumerate() {
+            if child_indices.is_empty() {
+                continue;
+            }
+
+            let child = format!("{name}{index}");
+            let kept = self.index(points, &child, child_indices, octree)?;
+            candidates.extend_from_slice(&kept);
+            child_candidates.push((child, kept));
+        }
+
+        let kept = self.subsample(points, candidates, &bounding_box, level);
+        let is_kept: HashSet<usize> = kept.iter().copied().collect();
+
+        for (child, child_indices) in child_candidates {
+            let remaining: Vec<usize> = child_indices
+                .into_iter()
+                .filter(|i| !is_kept.contains(i))
+                .collect();
+            octree.write_node(&self.metadata, &child, &points.select(&remaining))?;
+        }
+
+        Ok(kept)
+    }
+
+    // The candidates kept by a node of `level`, in order
+    fn subsample(
+        &self,
+        points: &PointBuffer,
+        candidates: Vec<usize>,
+        bounding_box: &Aabb,
+        level: usize,
+    ) -> Vec<usize> {
+        let spacing = self.metadata.spacing / (1_u64 << level) as f64;
+        let cell_of = |position: DVec3| {
+            ((position - bounding_box.min) / spacing)
+                .floor()
+                .as_i64vec3()
+        };
+
+        let mut grid: HashMap<I64Vec3, Vec<DVec3>> = HashMap::new();
+        let mut kept = Vec::new();
+
+        for i in candidates {
+            let position = points.positions[i];
+            let cell = cell_of(position);
+
+            let accepted = match self.options.sampling {
+                Sampling::Grid => !grid.contains_key(&cell),
+                Sampling::Poisson => (-1..=1).all(|x| {
+                    (-1..=1).all(|y| {
+                        (-1..=1).all(|z| {
+                            grid.get(&(cell + I64Vec3::new(x, y, z)))
+                                .is_none_or(|neighbors| {
+                                    neighbors.iter().all(|neighbor| {
+                                        neighbor.distance_squared(position) >= spacing * spacing
+                                    })
+                                })
+                        })
+                    })
+                }),
+            };
+
+            if accepted {
+                grid.entry(cell).or_default().push(position);
+                kept.push(i);
+            }
+        }
+
+        kept
+    }
+
+    // The chunk holding the position
+    fn chunk_of(&self, position: DVec3) -> String {
+        let mut name = "r".to_string();
+        let mut bounding_box = self.octree_bounding_box();
+
+        while !self.chunks.contains_key(&name) {
+            let index = child_index(bounding_box.center(), position);
+            bounding_box = bounding_box.child(index);
+            name.push((b'0' + index as u8) as char);
+        }
+
+        name
+    }
+
+    // Splits the chunk, and its children, while they hold too many points
+    fn split_chunk(&mut self, name: &str) -> Result<(), ConvertError> {
+        let mut queue = vec![name.to_string()];
+
+        while let Some(name) = queue.pop() {
+            let chunk = &self.chunks[&name];
+            let num_points = chunk.rows.len() / self.row_size + chunk.num_spilled;
+            if num_points <= self.options.max_chunk_points || name.len() > MAX_LEVEL {
+                continue;
+            }
+
+            let chunk = self.chunks.remove(&name).expect("chunk checked above");
+            let rows = self.read_chunk(&name, chunk)?;
+            self.rows_in_memory += rows.len() / self.row_size;
+
+            let center = self.node_bounding_box(&name).center();
+            let mut children: [Chunk; 8] = Default::default();
+            for row in rows.chunks_exact(self.row_size) {
+                let index = child_index(center, self.row_position(row));
+                children[index].rows.extend_from_slice(row);
+            }
+
+            for (index, child) in children.into_iter().enumerate() {
+                let child_name = format!("{name}{index}");
+                self.chunks.insert(child_name.clone(), child);
+                queue.push(child_name);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Writes the largest chunks held in memory to their files, down to half the budget
+    fn spill_chunks(&mut self) -> Result<(), ConvertError> {
+        let mut names: Vec<(usize, String)> = self
+            .chunks
+            .iter()
+            .map(|(name, chunk)| (chunk.rows.len(), name.clone()))
+            .collect();
+        names.sort_unstable_by(|a, b| b.cmp(a));
+
+        for (_, name) in names {
+            if self.rows_in_memory <= self.options.max_points_in_memory / 2 {
+                break;
+            }
+
+            let path = self.chunk_path(&name);
+            let chunk = self.chunks.get_mut(&name).expect("chunk listed above");
+            let num_rows = chunk.rows.len() / self.row_size;
+
+            // the first spill replaces any file left from a previous chunk of the same name
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(chunk.num_spilled > 0)
+                .truncate(chunk.num_spilled == 0)
+                .open(path)?
+                .write_all(&chunk.rows)?;
+
+            chunk.rows = Vec::new();
+            chunk.num_spilled += num_rows;
+            self.rows_in_memory -= num_rows;
+        }
+
+        Ok(())
+    }
+
+    // Removes the chunk from memory and returns its points
+    fn load_chunk(&mut self, name: &str) -> Result<PointBuffer, ConvertError> {
+        let chunk = self.chunks.remove(name).unwrap_or_default();
+        let rows = self.read_chunk(name, chunk)?;
+
+        let node = OctreeNode {
+            name: name.to_string(),
+            num_points: (rows.len() / self.row_size) as u32,
+            ..Default::default()
+        };
+
+        Ok(decode_points(
+            &self.rows_metadata,
+            &node,
+            &rows,
+            &AttributeSelection::All,
+        )?)
+    }
+
+    // The rows of the chunk, the spilled ones first, the file being removed
+    fn read_chunk(&mut self, name: &str, chunk: Chunk) -> Result<Vec<u8>, ConvertError> {
+        self.rows_in_memory -= chunk.rows.len() / self.row_size;
+        if chunk.num_spilled == 0 {
+            return Ok(chunk.rows);
+        }
+
+        let path = self.chunk_path(name);
+        let mut rows = fs::read(&path)?;
+        fs::remove_file(path)?;
+        rows.extend(chunk.rows);
+
+        Ok(rows)
+    }
+
+    fn chunk_path(&self, name: &str) -> PathBuf {
+        self.dir.join(".chunks").join(format!("{name}.bin"))
+    }
+
+    fn row_position(&self, row: &[u8]) -> DVec3 {
+        let bytes = &row[self.position_offset..self.position_offset + 12];
+        let coordinate = |i: usize| i32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+
+        DVec3::new(
+            coordinate(0) as f64,
+            coordinate(1) as f64,
+            coordinate(2) as f64,
+        ) * DVec3::from_array(self.metadata.scale)
+            + DVec3::from_array(self.metadata.offset)
+    }
+
+    fn octree_bounding_box(&self) -> Aabb {
+        self.metadata.bounding_box.clone().into()
+    }
+
+    fn node_bounding_box(&self, name: &str) -> Aabb {
+        name[1..]
+            .bytes()
+            .fold(self.octree_bounding_box(), |aabb, index| {
+                aabb.child((index - b'0') as usize)
+            })
+    }
+}
+
+impl OctreeWriter {
+    fn write_node(
+        &mut self,
+        metadata: &Metadata,
+        name: &str,
+        points: &PointBuffer,
+    ) -> Result<(), ConvertError> {
+        let mut node = WrittenNode::default();
+
+        if points.num_points > 0 {
+            let bytes = encode_points(metadata, name, points)?;
+            self.file.write_all(&bytes)?;
+            self.ranges.add(&metadata.attributes, points);
+
+            node.num_points = points.num_points as u32;
+            node.byte_offset = self.byte_offset;
+            node.byte_size = bytes.len() as u64;
+            self.byte_offset += node.byte_size;
+        }
+
+        self.nodes.insert(name.to_string(), node);
+        Ok(())
+    }
+}
+
+// Index of the child of the node centered on `center` holding the position
+fn child_index(center: DVec3, position: DVec3) -> usize {
+    ((position.x >= center.x) as usize) << 2
+        | ((position.y >= center.y) as usize) << 1
+        | (position.z >= center.z) as usize
+}
@@ -0,0 +1,121 @@
+use crate::decoder::interleaved;
+use crate::metadata::Metadata;
+use crate::octree::node::OctreeNode;
+use crate::point::buffer::{AttributeSelection, PointBuffer};
+use crate::point_cloud::LoadPointsError;
+use byteorder::{ByteOrder, LittleEndian};
+use glam::DVec3;
+
+/// Decodes a Potree 1.x `.bin` node.
+///
+/// Points are stored one after the other, each one holding all its attributes.
+/// Positions are stored as scaled `uint32` coordinates relative to the node's bounding box.
+pub(crate) fn decode(
+    metadata: &Metadata,
+    node: &OctreeNode,
+    buffer: &[u8],
+    selection: &AttributeSelection,
+) -> Result<PointBuffer, LoadPointsError> {
+    let point_size = interleaved::point_size(metadata);
+    // a node file holds exactly the points counted in the hierarchy
+    let num_points = node.num_points as usize;
+    if buffer.len() != num_points * point_size {
+        return Err(LoadPointsError::InvalidBufferSize {
+            node: node.name.clone(),
+            offset: node.byte_offset,
+            expected: num_points * point_size,
+            actual: buffer.len(),
+        });
+    }
+
+    let scale = &metadata.scale;
+    let min = node.bounding_box.min;
+
+    Ok(interleaved::decode(
+        metadata,
+        num_points,
+        buffer,
+        selection,
+        |bytes| {
+            let x = LittleEndian::read_u32(&bytes[0..4]);
+            let y = LittleEndian::read_u32(&bytes[4..8]);
+            let z = LittleEndian::read_u32(&bytes[8..12]);
+
+            min + DVec3::new(
+                x as f64 * scale[0],
+                y as f64 * scale[1],
+                z as f64 * scale[2],
+            )
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{AttributeMetadata, AttributeType, BoundingBox, HierarchyMetadata};
+    use crate::octree::aabb::Aabb;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            version: "1.8".to_string(),
+            name: String::new(),
+            description: String::new(),
+            points: 2,
+            projection: String::new(),
+            hierarchy: HierarchyMetadata {
+                first_chunk_size: 0,
+                step_size: 5,
+                depth: 0,
+            },
+            offset: [0.0; 3],
+            scale: [0.5; 3],
+            spacing: 1.0,
+            bounding_box: BoundingBox {
+                min: [0.0; 3],
+                max: [64.0; 3],
+            },
+            encoding: "BINARY".to_string(),
+            attributes: vec![
+                AttributeMetadata::new("position", AttributeType::UInt32, 3),
+                AttributeMetadata::new("intensity", AttributeType::UInt16, 1),
+            ],
+        }
+    }
+
+    fn node() -> OctreeNode {
+        OctreeNode {
+            name: "r7".to_string(),
+            bounding_box: Aabb::new(DVec3::splat(32.0), DVec3::splat(64.0)),
+            num_points: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decode_positions_relative_to_node() {
+        let mut buffer = Vec::new();
+        for (position, intensity) in [([0_u32, 2, 4], 7_u16), ([10, 20, 30], 8)] {
+            for coordinate in position {
+                buffer.extend(coordinate.to_le_bytes());
+            }
+            buffer.extend(intensity.to_le_bytes());
+        }
+
+        let points = decode(&metadata(), &node(), &buffer, &AttributeSelection::All).unwrap();
+
+        assert_eq!(
+            points.positions,
+            [DVec3::new(32.0, 33.0, 34.0), DVec3::new(37.0, 42.0, 47.0)]
+        );
+        let intensity = points.attribute("intensity").unwrap();
+        assert_eq!(intensity.get_f64(0, 0), Some(7.0));
+        assert_eq!(intensity.get_f64(1, 0), Some(8.0));
+
+        // a node file holds exactly the points of the hierarchy
+        assert!(matches!(
+            decode(&metadata(), &node(), &buffer[1..], &AttributeSelection::All),
+            Err(LoadPointsError::InvalidBufferSize { .. })
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::decoder::check_buffer_size;
+use crate::metadata::{AttributeType, Metadata};
+use crate::octree::node::OctreeNode;
+use crate::point::buffer::{
+    AttributeBuffer, AttributeData, AttributeSelection, PointBuffer, is_color_attribute,
+    is_position_attribute,
+};
+use crate::point_cloud::LoadPointsError;
+use byteorder::{ByteOrder, LittleEndian};
+use glam::DVec3;
+use std::io::{Cursor, Read};
+
+/// Decodes a node encoded with the `BROTLI` encoding.
+///
+/// Once decompressed, attributes are stored one after the other, each one
+/// holding the values of every point. Positions and colors are morton encoded.
+/// Attributes which are not selected are skipped.
+pub(crate) fn decode(
+    metadata: &Metadata,
+    node: &OctreeNode,
+    buffer: &[u8],
+    selection: &AttributeSelection,
+) -> Result<PointBuffer, LoadPointsError> {
+    let mut cursor = Cursor::new(buffer);
+    let mut input = brotli_decompressor::Decompressor::new(&mut cursor, 4096);
+    let mut decompressed_buffer = Vec::new();
+    input
+        .read_to_end(&mut decompressed_buffer)
+        .map_err(|error| LoadPointsError::Decompression {
+            node: node.name.clone(),
+            offset: node.byte_offset,
+            source: error,
+        })?;
+
+    let num_points = node.num_points as usize;
+    let expected_size = metadata
+        .attributes
+        .iter()
+        .map(|attribute| num_points * encoded_size(&attribute.name, attribute.size))
+        .sum();
+    check_buffer_size(node, expected_size, decompressed_buffer.len())?;
+    let mut byte_offset: usize = 0;
+
+    let mut points = PointBuffer::new(num_points);
+
+    for point_attribute in &metadata.attributes {
+        if !selection.contains(&point_attribute.name) {
+            byte_offset += num_points * encoded_size(&point_attribute.name, point_attribute.size);
+            continue;
+        }
+
+        if is_position_attribute(&point_attribute.name) {
+            let scale = &metadata.scale;
+            let offset = &metadata.offset;
+
+            points.positions.reserve(num_points);
+            for _ in 0..num_points {
+                let bytes = &decompressed_buffer[byte_offset..byte_offset + 16];
+                let (x, y, z) = read_morton_128(bytes);
+
+                points.positions.push(DVec3::new(
+                    x as f64 * scale[0] + offset[0],
+                    y as f64 * scale[1] + offset[1],
+                    z as f64 * scale[2] + offset[2],
+                ));
+
+                byte_offset += 16;
+            }
+        } else if is_color_attribute(&point_attribute.name) {
+            let num_elements = point_attribute.num_elements as usize;
+            let mut colors = Vec::with_capacity(num_points * num_elements);
+
+            for _ in 0..num_points {
+                let bytes = &decompressed_buffer[byte_offset..byte_offset + 8];
+                let (r, g, b) = read_morton_64(bytes);
+
+                colors.extend_from_slice(&[r, g, b]);
+                // alpha is not encoded
+                colors.extend(std::iter::repeat_n(
+                    u16::MAX,
+                    num_elements.saturating_sub(3),
+                ));
+
+                byte_offset += 8;
+            }
+
+            points.attributes.push(AttributeBuffer {
+                name: point_attribute.name.clone(),
+                num_elements: point_attribute.num_elements,
+                data: AttributeData::UInt16(colors),
+            });
+        } else {
+            let mut attribute = AttributeBuffer::with_capacity(point_attribute, num_points);
+            let size = point_attribute.size as usize;
+            let element_size = point_attribute.element_size as usize;
+
+            for _ in 0..num_points {
+                let bytes = &decompressed_buffer[byte_offset..byte_offset + size];
+
+                if point_attribute.r#type == AttributeType::Undefined {
+                    attribute.data.push_le_bytes(bytes);
+                } else {
+                    for element in bytes.chunks_exact(element_size) {
+                        attribute.data.push_le_bytes(element);
+                    }
+                }
+
+                byte_offset += size;
+            }
+
+            points.attributes.push(attribute);
+        }
+    }
+
+    Ok(points)
+}
+
+// Size of the attribute of one point, once decompressed
+fn encoded_size(name: &str, size: u16) -> usize {
+    if is_position_attribute(name) {
+        16
+    } else if is_color_attribute(name) {
+        8
+    } else {
+        size as usize
+    }
+}
+
+fn read_morton_64(bytes: &[u8]) -> (u16, u16, u16) {
+    let mc_0 = LittleEndian::read_u32(&bytes[4..8]);
+    let mc_1 = LittleEndian::read_u32(&bytes[0..4]);
+
+    decode_morton_64(mc_0, mc_1)
+}
+
+fn read_morton_128(bytes: &[u8]) -> (u32, u32, u32) {
+    let mc_0 = LittleEndian::read_u32(&bytes[4..8]);
+    let mc_1 = LittleEndian::read_u32(&bytes[0..4]);
+    let mc_2 = LittleEndian::read_u32(&bytes[12..16]);
+    let mc_3 = LittleEndian::read_u32(&bytes[8..12]);
+
+    decode_morton_128(mc_0, mc_1, mc_2, mc_3)
+}
+
+fn dealign_24b(mut morton: u32) -> u32 {
+    // Garde seulement chaque 3ème bit
+    morton &= 0x09249249; // 0b001001001001001001001001001001
+
+    morton = (morton | (morton >> 2)) & 0x030c30c3;
+    morton = (morton | (morton >> 4)) & 0x0300f00f;
+    morton = (morton | (morton >> 8)) & 0x030000ff;
+    morton = (morton | (morton >> 16)) & 0x000003ff;
+
+    morton
+}
+
+fn decode_morton_64(mc_0: u32, mc_1: u32) -> (u16, u16, u16) {
+    let r = dealign_24b(mc_1 & 0x00FFFFFF) | (dealign_24b((mc_1 >> 24) | (mc_0 << 8)) << 8);
+
+    let g = dealign_24b((mc_1 & 0x00FFFFFF) >> 1)
+        | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 1) << 8);
+
+    let b = dealign_24b((mc_1 & 0x00FFFFFF) >> 2)
+        | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 2) << 8);
+
+    (r as u16, g as u16, b as u16)
+}
+
+fn decode_morton_128(mc_0: u32, mc_1: u32, mc_2: u32, mc_3: u32) -> (u32, u32, u32) {
+    // First part (lower bits)
+    let mut x = dealign_24b(mc_3 & 0x00FFFFFF) | (dealign_24b((mc_3 >> 24) | (mc_2 << 8)) << 8);
+
+    let mut y = dealign_24b((mc_3 & 0x00FFFFFF) >> 1)
+        | (dealign_24b(((mc_3 >> 24) | (mc_2 << 8)) >> 1) << 8);
+
+    let mut z = dealign_24b((mc_3 & 0x00FFFFFF) >> 2)
+        | (dealign_24b(((mc_3 >> 24) | (mc_2 << 8)) >> 2) << 8);
+
+    // Second part (upper bits) - only if needed
+    if mc_1 != 0 || mc_2 != 0 {
+        x |= (dealign_24b(mc_1 & 0x00FFFFFF) << 16)
+            | (dealign_24b((mc_1 >> 24) | (mc_0 << 8)) << 24);
+
+        y |= (dealign_24b((mc_1 & 0x00FFFFFF) >> 1) << 16)
+            | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 1) << 24);
+
+        z |= (dealign_24b((mc_1 & 0x00FFFFFF) >> 2) << 16)
+            | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 2) << 24);
+    }
+
+    (x, y, z)
+}
@@ -0,0 +1,43 @@
+use crate::decoder::{check_buffer_size, interleaved};
+use crate::metadata::Metadata;
+use crate::octree::node::OctreeNode;
+use crate::point::buffer::{AttributeSelection, PointBuffer};
+use crate::point_cloud::LoadPointsError;
+use byteorder::{ByteOrder, LittleEndian};
+use glam::DVec3;
+
+/// Decodes a node encoded with the `DEFAULT` encoding.
+///
+/// Points are stored uncompressed, one after the other, each one holding all
+/// its attributes. Positions are stored as scaled `int32` coordinates.
+pub(crate) fn decode(
+    metadata: &Metadata,
+    node: &OctreeNode,
+    buffer: &[u8],
+    selection: &AttributeSelection,
+) -> Result<PointBuffer, LoadPointsError> {
+    let num_points = node.num_points as usize;
+    let point_size = interleaved::point_size(metadata);
+    check_buffer_size(node, num_points * point_size, buffer.len())?;
+
+    let scale = &metadata.scale;
+    let offset = &metadata.offset;
+
+    Ok(interleaved::decode(
+        metadata,
+        num_points,
+        buffer,
+        selection,
+        |bytes| {
+            let x = LittleEndian::read_i32(&bytes[0..4]);
+            let y = LittleEndian::read_i32(&bytes[4..8]);
+            let z = LittleEndian::read_i32(&bytes[8..12]);
+
+            DVec3::new(
+                x as f64 * scale[0] + offset[0],
+                y as f64 * scale[1] + offset[1],
+                z as f64 * scale[2] + offset[2],
+            )
+        },
+    ))
+}
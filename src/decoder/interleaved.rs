@@ -0,0 +1,74 @@
+use crate::metadata::{AttributeType, Metadata};
+use crate::point::buffer::{
+    AttributeBuffer, AttributeSelection, PointBuffer, is_position_attribute,
+};
+use glam::DVec3;
+
+/// Size of a point whose attributes are stored one after the other.
+pub(crate) fn point_size(metadata: &Metadata) -> usize {
+    metadata
+        .attributes
+        .iter()
+        .map(|attribute| attribute.size as usize)
+        .sum()
+}
+
+/// Decodes `num_points` points stored one after the other, each one holding all its
+/// attributes in the metadata order. The buffer must hold at least `num_points` points.
+///
+/// Positions are decoded from their first 12 bytes by `decode_position`.
+pub(crate) fn decode<F>(
+    metadata: &Metadata,
+    num_points: usize,
+    buffer: &[u8],
+    selection: &AttributeSelection,
+    decode_position: F,
+) -> PointBuffer
+where
+    F: Fn(&[u8]) -> DVec3,
+{
+    let point_size = point_size(metadata);
+    let mut points = PointBuffer::new(num_points);
+    let mut attribute_offset: usize = 0;
+
+    for point_attribute in &metadata.attributes {
+        let size = point_attribute.size as usize;
+
+        if !selection.contains(&point_attribute.name) {
+            attribute_offset += size;
+            continue;
+        }
+
+        if is_position_attribute(&point_attribute.name) {
+            points.positions.reserve(num_points);
+            for j in 0..num_points {
+                let byte_offset = j * point_size + attribute_offset;
+                points
+                    .positions
+                    .push(decode_position(&buffer[byte_offset..byte_offset + 12]));
+            }
+        } else {
+            let mut attribute = AttributeBuffer::with_capacity(point_attribute, num_points);
+            let element_size = point_attribute.element_size as usize;
+
+            for j in 0..num_points {
+                let byte_offset = j * point_size + attribute_offset;
+                let bytes = &buffer[byte_offset..byte_offset + size];
+
+                if point_attribute.r#type == AttributeType::Undefined {
+                    attribute.data.push_le_bytes(bytes);
+                } else {
+                    for element in bytes.chunks_exact(element_size) {
+                        attribute.data.push_le_bytes(element);
+                    }
+                }
+            }
+
+            points.attributes.push(attribute);
+        }
+
+        attribute_offset += size;
+    }
+
+    points
+}
@@ -0,0 +1,14 @@
+use crate::import::LasReader;
+use crate::point::buffer::{AttributeSelection, PointBuffer};
+use crate::point_cloud::LoadPointsError;
+use std::io::Cursor;
+
+/// Decodes a `.las` or `.laz` file.
+///
+/// Attributes are named after the ones written by PotreeConverter.
+pub(crate) fn decode(
+    buffer: Vec<u8>,
+    selection: &AttributeSelection,
+) -> Result<PointBuffer, LoadPointsError> {
+    Ok(LasReader::new(Cursor::new(buffer))?.read_all(selection)?)
+}
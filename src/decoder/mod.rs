@@ -0,0 +1,106 @@
+mod binary;
+mod brotli;
+mod default;
+mod interleaved;
+#[cfg(feature = "las")]
+mod las;
+
+use crate::metadata::{AttributeType, Metadata};
+use crate::octree::node::OctreeNode;
+use crate::point::buffer::{
+    AttributeBuffer, AttributeData, AttributeSelection, PointBuffer, is_color_attribute,
+    is_position_attribute,
+};
+use crate::point_cloud::LoadPointsError;
+
+/// Decodes the raw bytes of a node according to the encoding declared in the metadata.
+pub(crate) fn decode_points(
+    metadata: &Metadata,
+    node: &OctreeNode,
+    buffer: &[u8],
+    selection: &AttributeSelection,
+) -> Result<PointBuffer, LoadPointsError> {
+    if matches!(metadata.encoding.as_str(), "BROTLI" | "DEFAULT" | "BINARY") {
+        validate_attributes(metadata)?;
+    }
+
+    match metadata.encoding.as_str() {
+        "BROTLI" => brotli::decode(metadata, node, buffer, selection),
+        "DEFAULT" => default::decode(metadata, node, buffer, selection),
+        "BINARY" => binary::decode(metadata, node, buffer, selection),
+        #[cfg(feature = "las")]
+        "LAS" | "LAZ" => las::decode(buffer.to_vec(), selection),
+        _ => Err(LoadPointsError::EncodingUnimplemented(
+            metadata.encoding.clone(),
+        )),
+    }
+}
+
+/// Returns a buffer without points, with the columns the decoders create for the selection.
+pub(crate) fn empty_points(metadata: &Metadata, selection: &AttributeSelection) -> PointBuffer {
+    let mut points = PointBuffer::new(0);
+    points.attributes = metadata
+        .attributes
+        .iter()
+        .filter(|attribute| {
+            selection.contains(&attribute.name) && !is_position_attribute(&attribute.name)
+        })
+        .map(|attribute| {
+            // colors are always decoded as uint16 from the BROTLI encoding
+            if metadata.encoding == "BROTLI" && is_color_attribute(&attribute.name) {
+                AttributeBuffer {
+                    name: attribute.name.clone(),
+                    num_elements: attribute.num_elements,
+                    data: AttributeData::UInt16(Vec::new()),
+                }
+            } else {
+                AttributeBuffer::with_capacity(attribute, 0)
+            }
+        })
+        .collect();
+
+    points
+}
+
+// Check the attribute sizes, on which the decoders rely to slice the buffers
+fn validate_attributes(metadata: &Metadata) -> Result<(), LoadPointsError> {
+    for attribute in &metadata.attributes {
+        let valid = if is_position_attribute(&attribute.name) {
+            attribute.size >= 12
+        } else if attribute.r#type == AttributeType::Undefined {
+            true
+        } else {
+            attribute.element_size == attribute.r#type.element_size()
+                && attribute.size == attribute.element_size * attribute.num_elements
+        };
+
+        if !valid {
+            return Err(LoadPointsError::InvalidAttribute {
+                name: attribute.name.clone(),
+                size: attribute.size,
+                num_elements: attribute.num_elements,
+                element_size: attribute.element_size,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns an error if the buffer of the node is smaller than `expected`.
+pub(crate) fn check_buffer_size(
+    node: &OctreeNode,
+    expected: usize,
+    actual: usize,
+) -> Result<(), LoadPointsError> {
+    if actual < expected {
+        return Err(LoadPointsError::InvalidBufferSize {
+            node: node.name.clone(),
+            offset: node.byte_offset,
+            expected,
+            actual,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use crate::encoder::{extend_attribute, quantize};
+use crate::metadata::Metadata;
+use crate::point::buffer::{AttributeBuffer, PointBuffer, is_color_attribute};
+use crate::writer::WriteDatasetError;
+use glam::DVec3;
+use std::io::Write;
+
+// Compression level and window size of the encoder
+const QUALITY: u32 = 6;
+const WINDOW_SIZE: u32 = 22;
+
+/// Encodes a node with the `BROTLI` encoding.
+///
+/// Attributes are stored one after the other, each one holding the values of every
+/// point, then compressed. Positions and colors are morton encoded, the alpha channel
+/// of colors is dropped.
+pub(crate) fn encode(
+    metadata: &Metadata,
+    name: &str,
+    points: &PointBuffer,
+    columns: &[Option<&AttributeBuffer>],
+) -> Result<Vec<u8>, WriteDatasetError> {
+    let mut bytes = Vec::new();
+
+    for (attribute, column) in metadata.attributes.iter().zip(columns) {
+        match column {
+            None => {
+                for position in &points.positions {
+                    let quantized = quantize(metadata, *position);
+                    if quantized.cmplt(DVec3::ZERO).any()
+                        || quantized.cmpgt(DVec3::splat(u32::MAX as f64)).any()
+                    {
+                        return Err(WriteDatasetError::PositionOutOfRange {
+                            node: name.to_string(),
+                            position: *position,
+                        });
+                    }
+
+                    let [x, y, z] = quantized.to_array().map(|coordinate| coordinate as u32);
+                    bytes.extend(encode_morton_48(x >> 16, y >> 16, z >> 16).to_le_bytes());
+                    bytes.extend(encode_morton_48(x, y, z).to_le_bytes());
+                }
+            }
+            Some(column) if is_color_attribute(&attribute.name) => {
+                for i in 0..points.num_points {
+                    let [r, g, b] = [0, 1, 2]
+                        .map(|element| column.get_f64(i, element).unwrap_or_default() as u32);
+                    bytes.extend(encode_morton_48(r, g, b).to_le_bytes());
+                }
+            }
+            Some(column) => {
+                for i in 0..points.num_points {
+                    extend_attribute(attribute, column, i, &mut bytes);
+                }
+            }
+        }
+    }
+
+    let mut compressed = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(&mut compressed, 4096, QUALITY, WINDOW_SIZE);
+        writer
+            .write_all(&bytes)
+            .map_err(|error| WriteDatasetError::Compression {
+                node: name.to_string(),
+                source: error,
+            })?;
+    }
+
+    Ok(compressed)
+}
+
+// Interleaves the 16 low bits of the three values, x first
+fn encode_morton_48(x: u32, y: u32, z: u32) -> u64 {
+    spread_16b(x) | (spread_16b(y) << 1) | (spread_16b(z) << 2)
+}
+
+// Inserts two 0 bits between each of the 16 low bits
+fn spread_16b(value: u32) -> u64 {
+    let mut morton = (value & 0xffff) as u64;
+
+    morton = (morton | (morton << 16)) & 0x001f_0000_ff00_00ff;
+    morton = (morton | (morton << 8)) & 0x100f_00f0_0f00_f00f;
+    morton = (morton | (morton << 4)) & 0x10c3_0c30_c30c_30c3;
+    morton = (morton | (morton << 2)) & 0x1249_2492_4924_9249;
+
+    morton
+}
@@ -0,0 +1,48 @@
+use crate::encoder::{extend_attribute, quantize};
+use crate::metadata::Metadata;
+use crate::point::buffer::{AttributeBuffer, PointBuffer};
+use crate::writer::WriteDatasetError;
+use glam::DVec3;
+
+/// Encodes a node with the `DEFAULT` encoding.
+///
+/// Points are stored uncompressed, one after the other, each one holding all
+/// its attributes. Positions are stored as scaled `int32` coordinates.
+pub(crate) fn encode(
+    metadata: &Metadata,
+    name: &str,
+    points: &PointBuffer,
+    columns: &[Option<&AttributeBuffer>],
+) -> Result<Vec<u8>, WriteDatasetError> {
+    let point_size: usize = metadata
+        .attributes
+        .iter()
+        .map(|attribute| attribute.size as usize)
+        .sum();
+    let mut bytes = Vec::with_capacity(points.num_points * point_size);
+
+    for i in 0..points.num_points {
+        for (attribute, column) in metadata.attributes.iter().zip(columns) {
+            match column {
+                Some(column) => extend_attribute(attribute, column, i, &mut bytes),
+                None => {
+                    let position = quantize(metadata, points.positions[i]);
+                    if position.cmplt(DVec3::splat(i32::MIN as f64)).any()
+                        || position.cmpgt(DVec3::splat(i32::MAX as f64)).any()
+                    {
+                        return Err(WriteDatasetError::PositionOutOfRange {
+                            node: name.to_string(),
+                            position: points.positions[i],
+                        });
+                    }
+
+                    for coordinate in position.to_array() {
+                        bytes.extend((coordinate as i32).to_le_bytes());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(bytes)
+}
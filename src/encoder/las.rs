@@ -0,0 +1,128 @@
+use crate::point::buffer::{AttributeBuffer, AttributeData, PointBuffer};
+use glam::DVec3;
+use las::point::{Classification, Format};
+use las::{Builder, Color, Point, Transform, Vector, Writer};
+use std::io::{Seek, Write};
+
+/// Writes points to a `.las` file, or a `.laz` file if compressed, one buffer at a time.
+///
+/// Attributes are read by the names written by PotreeConverter, missing ones are left
+/// to their default value. Coordinates are stored with the given scale and offset.
+pub(crate) struct LasEncoder<W: Write + Seek + Send + Sync + 'static> {
+    writer: Writer<W>,
+    with_colors: bool,
+}
+
+impl<W: Write + Seek + Send + Sync + 'static> LasEncoder<W> {
+    pub fn new(
+        write: W,
+        scale: DVec3,
+        offset: DVec3,
+        with_colors: bool,
+        compressed: bool,
+    ) -> Result<Self, las::Error> {
+        // 1.4 formats, to store 8 bits classifications; 7 adds the colors to 6
+        let mut format = Format::new(if with_colors { 7 } else { 6 })?;
+        format.is_compressed = compressed;
+
+        let mut builder = Builder::from((1, 4));
+        builder.point_format = format;
+        builder.generating_software = "potree-rs".to_string();
+        builder.transforms = Vector {
+            x: Transform {
+                scale: scale.x,
+                offset: offset.x,
+            },
+            y: Transform {
+                scale: scale.y,
+                offset: offset.y,
+            },
+            z: Transform {
+                scale: scale.z,
+                offset: offset.z,
+            },
+        };
+
+        Ok(Self {
+            writer: Writer::new(write, builder.into_header()?)?,
+            with_colors,
+        })
+    }
+
+    pub fn write_points(&mut self, points: &PointBuffer) -> Result<(), las::Error> {
+        let colors = points.colors();
+        let intensity = points.attribute("intensity");
+        let return_number = points.attribute("return number");
+        let number_of_returns = points.attribute("number of returns");
+        let classification = points.attribute("classification");
+        let point_source_id = points.attribute("point source id");
+        let gps_time = points.attribute("gps-time");
+        let user_data = points.attribute("user data");
+
+        // 8 bits colors are scaled to 16 bits
+        let color_scale = match colors.map(|colors| &colors.data) {
+            Some(AttributeData::UInt8(_) | AttributeData::Int8(_)) => 257.0,
+            _ => 1.0,
+        };
+
+        for (i, position) in points.positions.iter().enumerate() {
+            let classification = value(classification, i) as u8;
+
+            self.writer.write_point(Point {
+                x: position.x,
+                y: position.y,
+                z: position.z,
+                intensity: value(intensity, i) as u16,
+                return_number: value(return_number, i) as u8,
+                number_of_returns: value(number_of_returns, i) as u8,
+                // 12 is reserved for the overlap flag since LAS 1.4
+                classification: Classification::new(classification)
+                    .unwrap_or(Classification::Unclassified),
+                is_overlap: classification == 12,
+                user_data: value(user_data, i) as u8,
+                point_source_id: value(point_source_id, i) as u16,
+                gps_time: Some(value(gps_time, i)),
+                color: self.with_colors.then(|| {
+                    let channel = |element| {
+                        let value = colors.and_then(|colors| colors.get_f64(i, element));
+                        (value.unwrap_or_default() * color_scale) as u16
+                    };
+                    Color::new(channel(0), channel(1), channel(2))
+                }),
+                ..Default::default()
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the header, updated with the bounds and number of points, and returns the writer.
+    pub fn finish(self) -> Result<W, las::Error> {
+        self.writer.into_inner()
+    }
+}
+
+/// Encodes the points as a `.las` file, or a `.laz` file if `compressed` is true.
+pub(crate) fn encode<W>(
+    points: &PointBuffer,
+    scale: DVec3,
+    offset: DVec3,
+    compressed: bool,
+    write: W,
+) -> Result<W, las::Error>
+where
+    W: Write + Seek + Send + Sync + 'static,
+{
+    let with_colors = points.colors().is_some();
+
+    let mut encoder = LasEncoder::new(write, scale, offset, with_colors, compressed)?;
+    encoder.write_points(points)?;
+    encoder.finish()
+}
+
+// First element of the attribute for point `index`, 0 if missing
+fn value(attribute: Option<&AttributeBuffer>, index: usize) -> f64 {
+    attribute
+        .and_then(|attribute| attribute.get_f64(index, 0))
+        .unwrap_or_default()
+}
@@ -0,0 +1,220 @@
+mod brotli;
+mod default;
+#[cfg(feature = "las")]
+pub(crate) mod las;
+pub(crate) mod pcd;
+pub(crate) mod ply;
+pub(crate) mod text;
+
+use crate::metadata::{AttributeMetadata, AttributeType, Metadata};
+use crate::point::buffer::{AttributeBuffer, PointBuffer, is_position_attribute, to_u8_color};
+use crate::writer::WriteDatasetError;
+use glam::DVec3;
+use std::io::{Seek, SeekFrom, Write};
+
+/// Encodes the points of a node according to the encoding declared in the metadata.
+pub(crate) fn encode_points(
+    metadata: &Metadata,
+    name: &str,
+    points: &PointBuffer,
+) -> Result<Vec<u8>, WriteDatasetError> {
+    let columns = columns(metadata, name, points)?;
+
+    match metadata.encoding.as_str() {
+        "BROTLI" => brotli::encode(metadata, name, points, &columns),
+        "DEFAULT" => default::encode(metadata, name, points, &columns),
+        _ => Err(WriteDatasetError::EncodingUnsupported(
+            metadata.encoding.clone(),
+        )),
+    }
+}
+
+// The column of each attribute of the metadata, `None` for positions.
+// Checks that every column holds the values of every point.
+fn columns<'a>(
+    metadata: &Metadata,
+    name: &str,
+    points: &'a PointBuffer,
+) -> Result<Vec<Option<&'a AttributeBuffer>>, WriteDatasetError> {
+    let mut columns = Vec::with_capacity(metadata.attributes.len());
+
+    for attribute in &metadata.attributes {
+        let (expected, actual, column) = if is_position_attribute(&attribute.name) {
+            (points.num_points, points.positions.len(), None)
+        } else {
+            let column = points.attribute(&attribute.name).ok_or_else(|| {
+                WriteDatasetError::MissingAttribute {
+                    node: name.to_string(),
+                    attribute: attribute.name.clone(),
+                }
+            })?;
+            (
+                points.num_points * values_per_point(attribute),
+                column.data.len(),
+                Some(column),
+            )
+        };
+
+        if expected != actual {
+            return Err(WriteDatasetError::InvalidAttributeLength {
+                node: name.to_string(),
+                attribute: attribute.name.clone(),
+                expected,
+                actual,
+            });
+        }
+
+        columns.push(column);
+    }
+
+    Ok(columns)
+}
+
+// Values stored per point in a column: elements, or bytes for undefined attributes
+fn values_per_point(attribute: &AttributeMetadata) -> usize {
+    if attribute.r#type == AttributeType::Undefined {
+        attribute.size as usize
+    } else {
+        attribute.num_elements as usize
+    }
+}
+
+// Appends the value of point `index` in the column, as stored in the metadata type
+fn extend_attribute(
+    attribute: &AttributeMetadata,
+    column: &AttributeBuffer,
+    index: usize,
+    bytes: &mut Vec<u8>,
+) {
+    let values = values_per_point(attribute);
+    for value in index * values..(index + 1) * values {
+        column.data.extend_le_bytes(value, attribute.r#type, bytes);
+    }
+}
+
+/// Returns the position as integer coordinates, scaled and offset as in the metadata.
+fn quantize(metadata: &Metadata, position: DVec3) -> DVec3 {
+    let scale = DVec3::from_array(metadata.scale);
+    let offset = DVec3::from_array(metadata.offset);
+
+    ((position - offset) / scale).round()
+}
+
+// Width of the point counts of the headers, written before the number of points is known
+const COUNT_WIDTH: usize = 20;
+
+/// Formats a point count of a header, zero padded so that it can be overwritten
+/// by `patch_count` once the number of points is known.
+pub(crate) fn padded_count(count: u64) -> String {
+    format!("{count:0COUNT_WIDTH$}")
+}
+
+/// Overwrites the point count written at `position`, then seeks back to the end.
+pub(crate) fn patch_count<W: Write + Seek>(
+    write: &mut W,
+    position: u64,
+    count: u64,
+) -> std::io::Result<()> {
+    write.seek(SeekFrom::Start(position))?;
+    write.write_all(padded_count(count).as_bytes())?;
+    write.seek(SeekFrom::End(0))?;
+    Ok(())
+}
+
+/// Value of element `element` of point `index` in the column, 0 if the column is missing.
+pub(crate) fn element_value(column: Option<&AttributeBuffer>, index: usize, element: usize) -> f64 {
+    column
+        .and_then(|column| column.get_f64(index, element))
+        .unwrap_or_default()
+}
+
+/// Color element `element` of point `index` in the column on 8 bits, 0 if the column is missing.
+pub(crate) fn color_value(column: Option<&AttributeBuffer>, index: usize, element: usize) -> u8 {
+    column
+        .and_then(|column| {
+            let value = column.get_f64(index, element)?;
+            Some(to_u8_color(&column.data, value))
+        })
+        .unwrap_or_default()
+}
+
+/// Appends element `element` of point `index` in the column as little-endian bytes
+/// of type `r#type`, 0 if the column is missing.
+pub(crate) fn extend_element(
+    column: Option<&AttributeBuffer>,
+    index: usize,
+    element: usize,
+    r#type: AttributeType,
+    bytes: &mut Vec<u8>,
+) {
+    let value = column.and_then(|column| {
+        let value = index * column.num_elements as usize + element;
+        (element < column.num_elements as usize && value < column.data.len())
+            .then_some((column, value))
+    });
+
+    match value {
+        Some((column, value)) => column.data.extend_le_bytes(value, r#type, bytes),
+        None => bytes.extend(std::iter::repeat_n(0, r#type.element_size() as usize)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::pcd::PcdEncoder;
+    use crate::encoder::ply::PlyEncoder;
+    use crate::point::buffer::AttributeData;
+    use std::io::Cursor;
+
+    // One point with a dark color, whose channels are all below 256
+    fn dark_point(data: AttributeData) -> (Vec<AttributeMetadata>, PointBuffer) {
+        let attribute = AttributeMetadata::new("rgb", data.r#type(), 3);
+        let points = PointBuffer {
+            num_points: 1,
+            positions: vec![DVec3::ZERO],
+            attributes: vec![AttributeBuffer {
+                name: "rgb".to_string(),
+                num_elements: 3,
+                data,
+            }],
+        };
+
+        (vec![attribute], points)
+    }
+
+    fn ply_line(data: AttributeData) -> String {
+        let (attributes, points) = dark_point(data);
+        let mut encoder = PlyEncoder::new(Cursor::new(Vec::new()), attributes, false).unwrap();
+        encoder.write_points(&points).unwrap();
+
+        let text = String::from_utf8(encoder.finish().unwrap().into_inner()).unwrap();
+        text.lines().last().unwrap().to_string()
+    }
+
+    fn pcd_line(data: AttributeData) -> String {
+        let (attributes, points) = dark_point(data);
+        let mut encoder = PcdEncoder::new(Cursor::new(Vec::new()), attributes, false).unwrap();
+        encoder.write_points(&points).unwrap();
+
+        let text = String::from_utf8(encoder.finish().unwrap().into_inner()).unwrap();
+        text.lines().last().unwrap().to_string()
+    }
+
+    #[test]
+    fn dark_16_bits_colors_are_scaled() {
+        // written as is to LAS files, so they must be scaled down like brighter ones
+        let data = AttributeData::UInt16(vec![0, 200, 255]);
+
+        assert_eq!(ply_line(data.clone()), "0 0 0 0 1 1");
+        assert_eq!(pcd_line(data), format!("0 0 0 {}", 0xFF000101_u32));
+    }
+
+    #[test]
+    fn colors_8_bits_are_kept() {
+        let data = AttributeData::UInt8(vec![0, 1, 2]);
+
+        assert_eq!(ply_line(data.clone()), "0 0 0 0 1 2");
+        assert_eq!(pcd_line(data), format!("0 0 0 {}", 0xFF000102_u32));
+    }
+}
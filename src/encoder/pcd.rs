@@ -0,0 +1,176 @@
+use crate::encoder::{color_value, element_value, extend_element, padded_count, patch_count};
+use crate::metadata::{AttributeMetadata, AttributeType};
+use crate::point::buffer::{AttributeBuffer, PointBuffer, is_color_attribute};
+use std::io::{Seek, Write};
+
+/// Writes points to a PCL `.pcd` file (version 0.7), binary or ASCII, one buffer at a time.
+///
+/// Positions are written as 8 bytes `x`, `y` and `z` fields. Colors are packed in a 4 bytes
+/// `rgb` (or `rgba`) field as PCL does, the other attributes keep their type, except 64 bits
+/// integers which are written as 8 bytes floats. Field names can't hold spaces, they are
+/// replaced by underscores.
+pub(crate) struct PcdEncoder<W: Write + Seek> {
+    write: W,
+    attributes: Vec<AttributeMetadata>,
+    binary: bool,
+    num_points: u64,
+    // where the number of points is written in the header, as WIDTH and as POINTS
+    count_positions: [u64; 2],
+}
+
+impl<W: Write + Seek> PcdEncoder<W> {
+    pub fn new(
+        mut write: W,
+        attributes: Vec<AttributeMetadata>,
+        binary: bool,
+    ) -> std::io::Result<Self> {
+        let start = write.stream_position()?;
+
+        let mut fields = vec!["x".to_string(), "y".to_string(), "z".to_string()];
+        let mut sizes = vec!["8"; 3];
+        let mut types = vec!["F"; 3];
+        let mut counts = vec!["1".to_string(); 3];
+        for attribute in &attributes {
+            let (name, r#type, count) = field(attribute);
+            let (size, type_name) = type_name(r#type);
+
+            fields.push(name);
+            sizes.push(size);
+            types.push(type_name);
+            counts.push(count.to_string());
+        }
+
+        let mut header = format!(
+            "# .PCD v0.7 - Point Cloud Data file format\nVERSION 0.7\nFIELDS {}\nSIZE {}\nTYPE {}\nCOUNT {}\nWIDTH ",
+            fields.join(" "),
+            sizes.join(" "),
+            types.join(" "),
+            counts.join(" "),
+        );
+        let width_position = start + header.len() as u64;
+        header.push_str(&padded_count(0));
+        header.push_str("\nHEIGHT 1\nVIEWPOINT 0 0 0 1 0 0 0\nPOINTS ");
+        let points_position = start + header.len() as u64;
+        header.push_str(&padded_count(0));
+        header.push_str(if binary {
+            "\nDATA binary\n"
+        } else {
+            "\nDATA ascii\n"
+        });
+
+        write.write_all(header.as_bytes())?;
+
+        Ok(Self {
+            write,
+            attributes,
+            binary,
+            num_points: 0,
+            count_positions: [width_position, points_position],
+        })
+    }
+
+    pub fn write_points(&mut self, points: &PointBuffer) -> std::io::Result<()> {
+        let columns: Vec<_> = self
+            .attributes
+            .iter()
+            .map(|attribute| (attribute, points.attribute(&attribute.name)))
+            .collect();
+
+        let mut bytes = Vec::new();
+        for (i, position) in points.positions.iter().enumerate() {
+            if self.binary {
+                for value in position.to_array() {
+                    bytes.extend(value.to_le_bytes());
+                }
+                for (attribute, column) in &columns {
+                    let (_, r#type, count) = field(attribute);
+                    if is_color(attribute) {
+                        bytes
+                            .extend(packed_color(*column, i, attribute.num_elements).to_le_bytes());
+                    } else {
+                        for element in 0..count {
+                            extend_element(*column, i, element, r#type, &mut bytes);
+                        }
+                    }
+                }
+            } else {
+                let mut line = format!("{} {} {}", position.x, position.y, position.z);
+                for (attribute, column) in &columns {
+                    if is_color(attribute) {
+                        let color = packed_color(*column, i, attribute.num_elements);
+                        line.push_str(&format!(" {color}"));
+                    } else {
+                        for element in 0..attribute.num_elements as usize {
+                            line.push_str(&format!(" {}", element_value(*column, i, element)));
+                        }
+                    }
+                }
+                line.push('\n');
+                bytes.extend(line.as_bytes());
+            }
+        }
+
+        self.write.write_all(&bytes)?;
+        self.num_points += points.positions.len() as u64;
+
+        Ok(())
+    }
+
+    /// Writes the number of points in the header and returns the writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        for position in self.count_positions {
+            patch_count(&mut self.write, position, self.num_points)?;
+        }
+        self.write.flush()?;
+        Ok(self.write)
+    }
+}
+
+fn is_color(attribute: &AttributeMetadata) -> bool {
+    is_color_attribute(&attribute.name) && attribute.num_elements >= 3
+}
+
+// Name, type and count of the field of an attribute
+fn field(attribute: &AttributeMetadata) -> (String, AttributeType, usize) {
+    if is_color(attribute) {
+        let name = if attribute.num_elements >= 4 {
+            "rgba"
+        } else {
+            "rgb"
+        };
+        return (name.to_string(), AttributeType::UInt32, 1);
+    }
+
+    let r#type = match attribute.r#type {
+        AttributeType::Int64 | AttributeType::UInt64 => AttributeType::Double,
+        AttributeType::Undefined => AttributeType::UInt8,
+        r#type => r#type,
+    };
+
+    (
+        attribute.name.replace(' ', "_"),
+        r#type,
+        attribute.num_elements as usize,
+    )
+}
+
+fn type_name(r#type: AttributeType) -> (&'static str, &'static str) {
+    match r#type {
+        AttributeType::Int8 => ("1", "I"),
+        AttributeType::Int16 => ("2", "I"),
+        AttributeType::Int32 => ("4", "I"),
+        AttributeType::UInt8 | AttributeType::Undefined => ("1", "U"),
+        AttributeType::UInt16 => ("2", "U"),
+        AttributeType::UInt32 => ("4", "U"),
+        AttributeType::Float => ("4", "F"),
+        AttributeType::Double | AttributeType::Int64 | AttributeType::UInt64 => ("8", "F"),
+    }
+}
+
+// Color of point `index` packed as PCL does, 0xAARRGGBB, opaque without alpha
+fn packed_color(column: Option<&AttributeBuffer>, index: usize, num_elements: u16) -> u32 {
+    let channel = |element| color_value(column, index, element) as u32;
+    let alpha = if num_elements >= 4 { channel(3) } else { 255 };
+
+    alpha << 24 | channel(0) << 16 | channel(1) << 8 | channel(2)
+}
@@ -0,0 +1,156 @@
+use crate::encoder::{color_value, element_value, extend_element, padded_count, patch_count};
+use crate::metadata::{AttributeMetadata, AttributeType};
+use crate::point::buffer::{PointBuffer, is_color_attribute};
+use std::io::{Seek, Write};
+
+/// Writes points to a `.ply` file, binary little endian or ASCII, one buffer at a time.
+///
+/// Positions are written as `double` properties. Colors are written as the usual `uchar`
+/// `red`, `green`, `blue` (and `alpha`) properties, the other attributes keep their type,
+/// except 64 bits integers which PLY doesn't have and are written as `double`.
+/// Attributes with several elements get a property per element, suffixed with its index.
+pub(crate) struct PlyEncoder<W: Write + Seek> {
+    write: W,
+    attributes: Vec<AttributeMetadata>,
+    binary: bool,
+    num_points: u64,
+    // where the number of points is written in the header
+    count_position: u64,
+}
+
+impl<W: Write + Seek> PlyEncoder<W> {
+    pub fn new(
+        mut write: W,
+        attributes: Vec<AttributeMetadata>,
+        binary: bool,
+    ) -> std::io::Result<Self> {
+        let format = if binary {
+            "binary_little_endian"
+        } else {
+            "ascii"
+        };
+
+        let mut header =
+            format!("ply\nformat {format} 1.0\ncomment generated by potree-rs\nelement vertex ");
+        let count_position = write.stream_position()? + header.len() as u64;
+        header.push_str(&padded_count(0));
+        header.push('\n');
+
+        for axis in ["x", "y", "z"] {
+            header.push_str(&format!("property double {axis}\n"));
+        }
+        for attribute in &attributes {
+            for (name, r#type) in properties(attribute) {
+                header.push_str(&format!("property {} {name}\n", type_name(r#type)));
+            }
+        }
+        header.push_str("end_header\n");
+
+        write.write_all(header.as_bytes())?;
+
+        Ok(Self {
+            write,
+            attributes,
+            binary,
+            num_points: 0,
+            count_position,
+        })
+    }
+
+    pub fn write_points(&mut self, points: &PointBuffer) -> std::io::Result<()> {
+        let columns: Vec<_> = self
+            .attributes
+            .iter()
+            .map(|attribute| {
+                let properties = properties(attribute);
+                let is_color = is_color(attribute);
+                (points.attribute(&attribute.name), properties, is_color)
+            })
+            .collect();
+
+        let mut bytes = Vec::new();
+        for (i, position) in points.positions.iter().enumerate() {
+            if self.binary {
+                for value in position.to_array() {
+                    bytes.extend(value.to_le_bytes());
+                }
+                for (column, properties, is_color) in &columns {
+                    for (element, (_, r#type)) in properties.iter().enumerate() {
+                        if *is_color {
+                            bytes.push(color_value(*column, i, element));
+                        } else {
+                            extend_element(*column, i, element, *r#type, &mut bytes);
+                        }
+                    }
+                }
+            } else {
+                let mut line = format!("{} {} {}", position.x, position.y, position.z);
+                for (column, properties, is_color) in &columns {
+                    for element in 0..properties.len() {
+                        if *is_color {
+                            line.push_str(&format!(" {}", color_value(*column, i, element)));
+                        } else {
+                            line.push_str(&format!(" {}", element_value(*column, i, element)));
+                        }
+                    }
+                }
+                line.push('\n');
+                bytes.extend(line.as_bytes());
+            }
+        }
+
+        self.write.write_all(&bytes)?;
+        self.num_points += points.positions.len() as u64;
+
+        Ok(())
+    }
+
+    /// Writes the number of points in the header and returns the writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        patch_count(&mut self.write, self.count_position, self.num_points)?;
+        self.write.flush()?;
+        Ok(self.write)
+    }
+}
+
+fn is_color(attribute: &AttributeMetadata) -> bool {
+    is_color_attribute(&attribute.name) && attribute.num_elements >= 3
+}
+
+// Name and type of the properties of an attribute
+fn properties(attribute: &AttributeMetadata) -> Vec<(String, AttributeType)> {
+    if is_color(attribute) {
+        return ["red", "green", "blue", "alpha"]
+            .into_iter()
+            .take(attribute.num_elements.min(4) as usize)
+            .map(|name| (name.to_string(), AttributeType::UInt8))
+            .collect();
+    }
+
+    let r#type = match attribute.r#type {
+        AttributeType::Int64 | AttributeType::UInt64 => AttributeType::Double,
+        r#type => r#type,
+    };
+    // property names can't hold spaces
+    let name = attribute.name.replace(' ', "_");
+
+    match attribute.num_elements {
+        1 => vec![(name, r#type)],
+        num_elements => (0..num_elements)
+            .map(|element| (format!("{name}_{element}"), r#type))
+            .collect(),
+    }
+}
+
+fn type_name(r#type: AttributeType) -> &'static str {
+    match r#type {
+        AttributeType::Int8 => "char",
+        AttributeType::Int16 => "short",
+        AttributeType::Int32 => "int",
+        AttributeType::UInt16 => "ushort",
+        AttributeType::UInt32 => "uint",
+        AttributeType::Float => "float",
+        AttributeType::Double | AttributeType::Int64 | AttributeType::UInt64 => "double",
+        AttributeType::UInt8 | AttributeType::Undefined => "uchar",
+    }
+}
@@ -0,0 +1,84 @@
+use crate::encoder::element_value;
+use crate::metadata::AttributeMetadata;
+use crate::point::buffer::{PointBuffer, is_position_attribute};
+use std::io::Write;
+
+/// Writes points as delimited text, one point per line, one buffer at a time.
+///
+/// The columns are the elements of the attributes, in order, a position attribute being
+/// written as the `x`, `y` and `z` world coordinates. They are optionally preceded by
+/// a header line with their names.
+pub(crate) struct TextEncoder<W: Write> {
+    write: W,
+    attributes: Vec<AttributeMetadata>,
+    delimiter: char,
+}
+
+impl<W: Write> TextEncoder<W> {
+    pub fn new(
+        mut write: W,
+        attributes: Vec<AttributeMetadata>,
+        delimiter: char,
+        header: bool,
+    ) -> std::io::Result<Self> {
+        if header {
+            let mut names = Vec::new();
+            for attribute in &attributes {
+                if is_position_attribute(&attribute.name) {
+                    names.extend(["x".to_string(), "y".to_string(), "z".to_string()]);
+                    continue;
+                }
+
+                match attribute.num_elements {
+                    1 => names.push(attribute.name.clone()),
+                    num_elements => names.extend(
+                        (0..num_elements).map(|element| format!("{}[{element}]", attribute.name)),
+                    ),
+                }
+            }
+            writeln!(write, "{}", names.join(&delimiter.to_string()))?;
+        }
+
+        Ok(Self {
+            write,
+            attributes,
+            delimiter,
+        })
+    }
+
+    pub fn write_points(&mut self, points: &PointBuffer) -> std::io::Result<()> {
+        let columns: Vec<_> = self
+            .attributes
+            .iter()
+            .map(|attribute| {
+                let is_position = is_position_attribute(&attribute.name);
+                (attribute, is_position, points.attribute(&attribute.name))
+            })
+            .collect();
+        let delimiter = self.delimiter.to_string();
+
+        let mut text = String::new();
+        for (i, position) in points.positions.iter().enumerate() {
+            let mut values = Vec::new();
+            for (attribute, is_position, column) in &columns {
+                if *is_position {
+                    values.extend(position.to_array().map(|value| value.to_string()));
+                } else {
+                    values.extend(
+                        (0..attribute.num_elements as usize)
+                            .map(|element| element_value(*column, i, element).to_string()),
+                    );
+                }
+            }
+            text.push_str(&values.join(&delimiter));
+            text.push('\n');
+        }
+
+        self.write.write_all(text.as_bytes())
+    }
+
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.write.flush()?;
+        Ok(self.write)
+    }
+}
@@ -0,0 +1,365 @@
+//! Exporting the points of a point cloud to LAS/LAZ, PLY, PCD or delimited text files.
+//!
+//! The points are loaded and written one node at a time, so the exported files may be
+//! much larger than the memory.
+
+#[cfg(feature = "las")]
+use crate::encoder::las::LasEncoder;
+use crate::encoder::pcd::PcdEncoder;
+use crate::encoder::ply::PlyEncoder;
+use crate::encoder::text::TextEncoder;
+use crate::metadata::{AttributeMetadata, AttributeType, Metadata};
+use crate::octree::NodeId;
+use crate::octree::aabb::Aabb;
+use crate::octree::region::Region;
+#[cfg(feature = "las")]
+use crate::point::buffer::is_color_attribute;
+use crate::point::buffer::{AttributeSelection, PointBuffer, is_position_attribute};
+use crate::point_cloud::{DEFAULT_MAX_CONCURRENT_REQUESTS, PotreePointCloud};
+use crate::query::{QueryDepth, QueryError, with_positions};
+use futures::StreamExt;
+use futures::stream;
+#[cfg(feature = "las")]
+use glam::DVec3;
+use std::io::{Seek, Write};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Error querying points: {0}")]
+    QueryError(#[from] QueryError),
+
+    #[cfg(feature = "las")]
+    #[error("Error writing LAS file: {0}")]
+    Las(#[from] las::Error),
+
+    #[error("Error writing points: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The file format of an export.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// LAS 1.4, or LAZ if compressed, which requires the `laz` feature.
+    ///
+    /// The scale and offset of the point cloud are used for the LAS coordinates. Intensity,
+    /// return numbers, classification, point source id, gps-time, user data and colors are
+    /// mapped to the point record fields, the other attributes are dropped.
+    #[cfg(feature = "las")]
+    Las { compressed: bool },
+    /// PLY, binary little endian or ASCII, with `double` positions.
+    Ply { binary: bool },
+    /// PCL's PCD, binary or ASCII, with 8 bytes positions and colors packed in `rgb`.
+    Pcd { binary: bool },
+    /// Delimited text, one point per line: the position then the elements of the attributes.
+    /// Writers which can't seek are written with `PotreePointCloud::export_text`.
+    Text { delimiter: char, header: bool },
+}
+
+impl ExportFormat {
+    /// Space separated values, without header.
+    pub fn xyz() -> Self {
+        ExportFormat::Text {
+            delimiter: ' ',
+            header: false,
+        }
+    }
+
+    /// Comma separated values, with a header line.
+    pub fn csv() -> Self {
+        ExportFormat::Text {
+            delimiter: ',',
+            header: true,
+        }
+    }
+}
+
+enum Encoder<W: Write + Seek + Send + Sync + 'static> {
+    #[cfg(feature = "las")]
+    Las(LasEncoder<W>),
+    Ply(PlyEncoder<W>),
+    Pcd(PcdEncoder<W>),
+    Text(TextEncoder<W>),
+}
+
+// Writes the exported points one buffer at a time, then returns the writer
+trait PointWriter<W> {
+    fn write_points(&mut self, points: &PointBuffer) -> Result<(), ExportError>;
+
+    fn finish(self) -> Result<W, ExportError>;
+}
+
+impl PotreePointCloud {
+    /// Writes the points of the nodes in the format, with the selected attributes.
+    ///
+    /// The world positions are always written. Attributes whose type is unknown are dropped.
+    /// The writer must be seekable, as the LAS, PLY and PCD headers are completed at the end,
+    /// see [`Self::export_text`] for other writers.
+    pub async fn export<W>(
+        &self,
+        nodes: &[NodeId],
+        format: ExportFormat,
+        selection: &AttributeSelection,
+        write: W,
+    ) -> Result<W, ExportError>
+    where
+        W: Write + Seek + Send + Sync + 'static,
+    {
+        let selection = with_positions(selection);
+        let encoder = Encoder::new(self.metadata(), format, &selection, write)?;
+
+        self.write_points(nodes.to_vec(), None::<&Aabb>, &selection, encoder)
+            .await
+    }
+
+    /// Writes the points inside the region, fetched down to `depth`, in the format,
+    /// with the selected attributes.
+    ///
+    /// The hierarchy chunks of the proxies intersecting the region are loaded on the way.
+    pub async fn export_region<R, W>(
+        &mut self,
+        region: &R,
+        depth: QueryDepth,
+        format: ExportFormat,
+        selection: &AttributeSelection,
+        write: W,
+    ) -> Result<W, ExportError>
+    where
+        R: Region,
+        W: Write + Seek + Send + Sync + 'static,
+    {
+        let nodes = self
+            .query_nodes(region, depth)
+            .await
+            .map_err(QueryError::from)?;
+        let selection = with_positions(selection);
+        let encoder = Encoder::new(self.metadata(), format, &selection, write)?;
+
+        self.write_points(nodes, Some(region), &selection, encoder)
+            .await
+    }
+
+    /// Writes the points of the nodes as delimited text, one point per line, with the
+    /// selected attributes, as [`ExportFormat::Text`] does.
+    ///
+    /// The text is written in order, so the writer doesn't need to be seekable.
+    pub async fn export_text<W: Write>(
+        &self,
+        nodes: &[NodeId],
+        delimiter: char,
+        header: bool,
+        selection: &AttributeSelection,
+        write: W,
+    ) -> Result<W, ExportError> {
+        let selection = with_positions(selection);
+        let encoder = text_encoder(self.metadata(), &selection, delimiter, header, write)?;
+
+        self.write_points(nodes.to_vec(), None::<&Aabb>, &selection, encoder)
+            .await
+    }
+
+    /// Writes the points inside the region, fetched down to `depth`, as delimited text,
+    /// with the selected attributes. The writer doesn't need to be seekable.
+    pub async fn export_region_text<R, W>(
+        &mut self,
+        region: &R,
+        depth: QueryDepth,
+        delimiter: char,
+        header: bool,
+        selection: &AttributeSelection,
+        write: W,
+    ) -> Result<W, ExportError>
+    where
+        R: Region,
+        W: Write,
+    {
+        let nodes = self
+            .query_nodes(region, depth)
+            .await
+            .map_err(QueryError::from)?;
+        let selection = with_positions(selection);
+        let encoder = text_encoder(self.metadata(), &selection, delimiter, header, write)?;
+
+        self.write_points(nodes, Some(region), &selection, encoder)
+            .await
+    }
+
+    /// Writes the points of the nodes as a LAS 1.4 file, or a LAZ file if `compressed` is true,
+    /// which requires the `laz` feature.
+    ///
+    /// The scale and offset of the point cloud are used for the LAS coordinates. Intensity,
+    /// return numbers, classification, point source id, gps-time, user data and colors are
+    /// mapped to the point record fields, the other attributes are dropped.
+    #[cfg(feature = "las")]
+    pub async fn export_las<W>(
+        &self,
+        nodes: &[NodeId],
+        compressed: bool,
+        write: W,
+    ) -> Result<W, ExportError>
+    where
+        W: Write + Seek + Send + Sync + 'static,
+    {
+        self.export(
+            nodes,
+            ExportFormat::Las { compressed },
+            &AttributeSelection::All,
+            write,
+        )
+        .await
+    }
+
+    /// Writes the points inside the region as a LAS 1.4 file, or a LAZ file if `compressed`
+    /// is true, fetched down to `depth`.
+    ///
+    /// The hierarchy chunks of the proxies intersecting the region are loaded on the way,
+    /// and the points are written one node at a time.
+    #[cfg(feature = "las")]
+    pub async fn export_region_las<R, W>(
+        &mut self,
+        region: &R,
+        depth: QueryDepth,
+        compressed: bool,
+        write: W,
+    ) -> Result<W, ExportError>
+    where
+        R: Region,
+        W: Write + Seek + Send + Sync + 'static,
+    {
+        self.export_region(
+            region,
+            depth,
+            ExportFormat::Las { compressed },
+            &AttributeSelection::All,
+            write,
+        )
+        .await
+    }
+
+    // Loads the points of the nodes with the selection, including positions, and writes them
+    async fn write_points<R, W, E>(
+        &self,
+        nodes: Vec<NodeId>,
+        region: Option<&R>,
+        selection: &AttributeSelection,
+        mut encoder: E,
+    ) -> Result<W, ExportError>
+    where
+        R: Region,
+        E: PointWriter<W>,
+    {
+        let mut buffers = stream::iter(nodes)
+            .map(|node_id| self.load_point_buffer_with(node_id, selection))
+            .buffered(DEFAULT_MAX_CONCURRENT_REQUESTS);
+
+        while let Some(buffer) = buffers.next().await {
+            let buffer = buffer.map_err(QueryError::from)?;
+
+            match region {
+                Some(region) => encoder
+                    .write_points(&buffer.filter(|i| region.contains_point(buffer.positions[i])))?,
+                None => encoder.write_points(&buffer)?,
+            }
+        }
+
+        encoder.finish()
+    }
+}
+
+impl<W: Write + Seek + Send + Sync + 'static> Encoder<W> {
+    fn new(
+        metadata: &Metadata,
+        format: ExportFormat,
+        selection: &AttributeSelection,
+        write: W,
+    ) -> Result<Self, ExportError> {
+        let attributes = exported_attributes(metadata, selection);
+
+        Ok(match format {
+            #[cfg(feature = "las")]
+            ExportFormat::Las { compressed } => Encoder::Las(LasEncoder::new(
+                write,
+                DVec3::from_array(metadata.scale),
+                DVec3::from_array(metadata.offset),
+                attributes
+                    .iter()
+                    .any(|attribute| is_color_attribute(&attribute.name)),
+                compressed,
+            )?),
+            ExportFormat::Ply { binary } => {
+                Encoder::Ply(PlyEncoder::new(write, attributes, binary)?)
+            }
+            ExportFormat::Pcd { binary } => {
+                Encoder::Pcd(PcdEncoder::new(write, attributes, binary)?)
+            }
+            ExportFormat::Text { delimiter, header } => {
+                Encoder::Text(text_encoder(metadata, selection, delimiter, header, write)?)
+            }
+        })
+    }
+}
+
+impl<W: Write + Seek + Send + Sync + 'static> PointWriter<W> for Encoder<W> {
+    fn write_points(&mut self, points: &PointBuffer) -> Result<(), ExportError> {
+        match self {
+            #[cfg(feature = "las")]
+            Encoder::Las(encoder) => encoder.write_points(points)?,
+            Encoder::Ply(encoder) => encoder.write_points(points)?,
+            Encoder::Pcd(encoder) => encoder.write_points(points)?,
+            Encoder::Text(encoder) => encoder.write_points(points)?,
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<W, ExportError> {
+        Ok(match self {
+            #[cfg(feature = "las")]
+            Encoder::Las(encoder) => encoder.finish()?,
+            Encoder::Ply(encoder) => encoder.finish()?,
+            Encoder::Pcd(encoder) => encoder.finish()?,
+            Encoder::Text(encoder) => encoder.finish()?,
+        })
+    }
+}
+
+impl<W: Write> PointWriter<W> for TextEncoder<W> {
+    fn write_points(&mut self, points: &PointBuffer) -> Result<(), ExportError> {
+        Ok(TextEncoder::write_points(self, points)?)
+    }
+
+    fn finish(self) -> Result<W, ExportError> {
+        Ok(TextEncoder::finish(self)?)
+    }
+}
+
+// The selected attributes written as values, positions being written apart
+fn exported_attributes(
+    metadata: &Metadata,
+    selection: &AttributeSelection,
+) -> Vec<AttributeMetadata> {
+    metadata
+        .attributes
+        .iter()
+        .filter(|attribute| {
+            !is_position_attribute(&attribute.name)
+                && attribute.r#type != AttributeType::Undefined
+                && selection.contains(&attribute.name)
+        })
+        .cloned()
+        .collect()
+}
+
+// Text columns: the position, then the selected attributes
+fn text_encoder<W: Write>(
+    metadata: &Metadata,
+    selection: &AttributeSelection,
+    delimiter: char,
+    header: bool,
+    write: W,
+) -> std::io::Result<TextEncoder<W>> {
+    let mut columns = vec![AttributeMetadata::new("position", AttributeType::Double, 3)];
+    columns.extend(exported_attributes(metadata, selection));
+
+    TextEncoder::new(write, columns, delimiter, header)
+}
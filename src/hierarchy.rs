@@ -10,3 +10,12 @@ pub struct HierarchyNodeEntry {
     pub byte_offset: u64,
     pub byte_size: u64,
 }
+
+/// An entry of a Potree 1.x `.hrc` hierarchy file.
+#[binrw]
+#[derive(Clone, Debug)]
+#[br(little)]
+pub struct LegacyHierarchyNodeEntry {
+    pub child_mask: u8,
+    pub num_points: u32,
+}
@@ -0,0 +1,317 @@
+//! Reading `.las` and `.laz` files as point buffers, with the attributes written by
+//! PotreeConverter, so they can be compared to the nodes of a point cloud or written
+//! as a Potree dataset.
+
+use crate::metadata::{AttributeMetadata, AttributeType};
+use crate::octree::aabb::Aabb;
+use crate::point::buffer::{AttributeBuffer, AttributeData, AttributeSelection, PointBuffer};
+use glam::DVec3;
+use las::point::Format;
+use las::{Header, PointData, Reader};
+use std::io::{Read, Seek};
+
+/// Reads the points of a `.las` file, or a `.laz` file with the `laz` feature, in batches.
+///
+/// The attributes are the ones PotreeConverter creates for the point format of the file:
+/// `intensity`, `return number`, `number of returns`, `classification`, `scan angle rank`
+/// (or `classification flags` and `scan angle` for the LAS 1.4 formats), `user data`,
+/// `point source id`, then `gps-time` and `rgb` if the format has them. Extra bytes are ignored.
+pub struct LasReader {
+    reader: Reader,
+}
+
+impl LasReader {
+    pub fn new<R: Read + Seek + Send + Sync + 'static>(read: R) -> Result<Self, las::Error> {
+        Ok(Self {
+            reader: Reader::new(read)?,
+        })
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn from_path<P: AsRef<std::path::Path>>(path: P) -> Result<Self, las::Error> {
+        Ok(Self {
+            reader: Reader::from_path(path)?,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        self.reader.header()
+    }
+
+    pub fn num_points(&self) -> u64 {
+        self.header().number_of_points()
+    }
+
+    pub fn scale(&self) -> DVec3 {
+        let transforms = self.header().transforms();
+        DVec3::new(transforms.x.scale, transforms.y.scale, transforms.z.scale)
+    }
+
+    pub fn offset(&self) -> DVec3 {
+        let transforms = self.header().transforms();
+        DVec3::new(
+            transforms.x.offset,
+            transforms.y.offset,
+            transforms.z.offset,
+        )
+    }
+
+    /// Bounding box of the points, from the header.
+    pub fn bounding_box(&self) -> Aabb {
+        let bounds = self.header().bounds();
+        Aabb::new(
+            DVec3::new(bounds.min.x, bounds.min.y, bounds.min.z),
+            DVec3::new(bounds.max.x, bounds.max.y, bounds.max.z),
+        )
+    }
+
+    /// The attributes of the points, `position` first, as they are described in the
+    /// `metadata.json` of PotreeConverter.
+    pub fn attributes(&self) -> Vec<AttributeMetadata> {
+        let format = self.header().point_format();
+        let bounding_box = self.bounding_box();
+
+        let mut position = AttributeMetadata::new("position", AttributeType::Int32, 3);
+        position.min = bounding_box.min.as_vec3().to_array().to_vec();
+        position.max = bounding_box.max.as_vec3().to_array().to_vec();
+
+        std::iter::once(position)
+            .chain(format_attributes(format))
+            .collect()
+    }
+
+    /// Reads the next `n` points, or fewer at the end of the file.
+    pub fn read_points(
+        &mut self,
+        n: u64,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, las::Error> {
+        let point_data = self.reader.read_points(n)?;
+        self.decode(point_data, selection)
+    }
+
+    /// Reads the remaining points.
+    pub fn read_all(&mut self, selection: &AttributeSelection) -> Result<PointBuffer, las::Error> {
+        let point_data = self.reader.read_all()?;
+        self.decode(point_data, selection)
+    }
+
+    fn decode(
+        &self,
+        point_data: PointData,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, las::Error> {
+        let format = self.header().point_format();
+        let num_points = point_data.len();
+
+        let mut points = PointBuffer::new(num_points);
+        let with_positions = selection.contains("position");
+        if with_positions {
+            points.positions.reserve(num_points);
+        }
+
+        let mut intensity = Vec::with_capacity(num_points);
+        let mut return_number = Vec::with_capacity(num_points);
+        let mut number_of_returns = Vec::with_capacity(num_points);
+        let mut classification = Vec::with_capacity(num_points);
+        let mut classification_flags = Vec::new();
+        let mut scan_angle_rank = Vec::new();
+        let mut scan_angle = Vec::new();
+        let mut user_data = Vec::with_capacity(num_points);
+        let mut point_source_id = Vec::with_capacity(num_points);
+        let mut gps_time = Vec::new();
+        let mut rgb = Vec::new();
+
+        for point in point_data.points() {
+            let point = point?;
+
+            if with_positions {
+                points.positions.push(DVec3::new(point.x, point.y, point.z));
+            }
+            intensity.push(point.intensity);
+            return_number.push(point.return_number);
+            number_of_returns.push(point.number_of_returns);
+            classification.push(u8::from(point.classification));
+            user_data.push(point.user_data);
+            point_source_id.push(point.point_source_id);
+
+            if format.is_extended {
+                classification_flags.push(
+                    point.is_synthetic as u8
+                        | (point.is_key_point as u8) << 1
+                        | (point.is_withheld as u8) << 2
+                        | (point.is_overlap as u8) << 3,
+                );
+                // stored in steps of 0.006°
+                scan_angle.push((point.scan_angle / 0.006).round() as i16);
+            } else {
+                scan_angle_rank.push(point.scan_angle as i8);
+            }
+            if format.has_gps_time {
+                gps_time.push(point.gps_time.unwrap_or_default());
+            }
+            if format.has_color {
+                let color = point.color.unwrap_or_default();
+                rgb.extend_from_slice(&[color.red, color.green, color.blue]);
+            }
+        }
+
+        let mut data = [
+            ("intensity", AttributeData::UInt16(intensity)),
+            ("return number", AttributeData::UInt8(return_number)),
+            ("number of returns", AttributeData::UInt8(number_of_returns)),
+            ("classification", AttributeData::UInt8(classification)),
+            (
+                "classification flags",
+                AttributeData::UInt8(classification_flags),
+            ),
+            ("scan angle rank", AttributeData::Int8(scan_angle_rank)),
+            ("scan angle", AttributeData::Int16(scan_angle)),
+            ("user data", AttributeData::UInt8(user_data)),
+            ("point source id", AttributeData::UInt16(point_source_id)),
+            ("gps-time", AttributeData::Double(gps_time)),
+            ("rgb", AttributeData::UInt16(rgb)),
+        ];
+
+        points.attributes = columns(format)
+            .into_iter()
+            .filter(|(name, _, _)| selection.contains(name))
+            .map(|(name, _, num_elements)| {
+                let (_, column) = data
+                    .iter_mut()
+                    .find(|(column, _)| *column == name)
+                    .expect("every column is decoded, shouldn't happen");
+
+                AttributeBuffer {
+                    name: name.to_string(),
+                    num_elements,
+                    data: std::mem::replace(column, AttributeData::UInt8(Vec::new())),
+                }
+            })
+            .collect();
+
+        Ok(points)
+    }
+}
+
+/// Size of the LAS 1.0 to 1.2 header, the smallest one.
+pub(crate) const MIN_HEADER_SIZE: usize = 227;
+
+// Offset of the point data record format, the same in every version of the header
+const POINT_FORMAT_OFFSET: usize = 104;
+
+/// Returns the attributes of the points, without `position`, from the start of the header
+/// of a `.las` or `.laz` file, so they are listed without reading the whole file.
+pub(crate) fn header_attributes(header: &[u8]) -> Result<Vec<AttributeMetadata>, las::Error> {
+    if header.len() <= POINT_FORMAT_OFFSET {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    if &header[0..4] != b"LASF" {
+        let signature = header[0..4].try_into().expect("4 bytes signature");
+        return Err(las::Error::InvalidFileSignature(signature));
+    }
+
+    let format = Format::new(header[POINT_FORMAT_OFFSET])?;
+
+    Ok(format_attributes(&format))
+}
+
+fn format_attributes(format: &Format) -> Vec<AttributeMetadata> {
+    columns(format)
+        .into_iter()
+        .map(|(name, r#type, num_elements)| AttributeMetadata::new(name, r#type, num_elements))
+        .collect()
+}
+
+// Name, type and number of elements of the attributes of the point format
+fn columns(format: &Format) -> Vec<(&'static str, AttributeType, u16)> {
+    let mut columns = vec![
+        ("intensity", AttributeType::UInt16, 1),
+        ("return number", AttributeType::UInt8, 1),
+        ("number of returns", AttributeType::UInt8, 1),
+        ("classification", AttributeType::UInt8, 1),
+    ];
+
+    if format.is_extended {
+        columns.push(("classification flags", AttributeType::UInt8, 1));
+        columns.push(("scan angle", AttributeType::Int16, 1));
+    } else {
+        columns.push(("scan angle rank", AttributeType::Int8, 1));
+    }
+
+    columns.push(("user data", AttributeType::UInt8, 1));
+    columns.push(("point source id", AttributeType::UInt16, 1));
+
+    if format.has_gps_time {
+        columns.push(("gps-time", AttributeType::Double, 1));
+    }
+    if format.has_color {
+        columns.push(("rgb", AttributeType::UInt16, 3));
+    }
+
+    columns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use las::{Builder, Color, Point, Writer};
+    use std::io::Cursor;
+
+    // A file holding one point in the point format, with a header of this version
+    fn las_file(version: (u8, u8), format: u8) -> Vec<u8> {
+        let mut builder = Builder::from(version);
+        let format = Format::new(format).unwrap();
+        builder.point_format = format;
+        let mut writer =
+            Writer::new(Cursor::new(Vec::new()), builder.into_header().unwrap()).unwrap();
+        writer
+            .write_point(Point {
+                gps_time: format.has_gps_time.then_some(0.0),
+                color: format.has_color.then(Color::default),
+                ..Default::default()
+            })
+            .unwrap();
+
+        writer.into_inner().unwrap().into_inner()
+    }
+
+    #[test]
+    fn header_attributes_match_reader() {
+        for (version, format) in [((1, 2), 0), ((1, 2), 3), ((1, 4), 6), ((1, 4), 7)] {
+            let file = las_file(version, format);
+            let reader = LasReader::new(Cursor::new(file.clone())).unwrap();
+
+            let attributes = header_attributes(&file[..MIN_HEADER_SIZE]).unwrap();
+
+            let columns = |attributes: &[AttributeMetadata]| {
+                attributes
+                    .iter()
+                    .map(|attribute| {
+                        (
+                            attribute.name.clone(),
+                            attribute.r#type,
+                            attribute.num_elements,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                columns(&attributes),
+                columns(&reader.attributes()[1..]),
+                "format {format}"
+            );
+        }
+    }
+
+    #[test]
+    fn header_attributes_rejects_invalid_headers() {
+        let file = las_file((1, 2), 3);
+
+        assert!(header_attributes(&file[..100]).is_err());
+        assert!(matches!(
+            header_attributes(&[0; MIN_HEADER_SIZE]),
+            Err(las::Error::InvalidFileSignature(_))
+        ));
+    }
+}
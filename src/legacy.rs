@@ -0,0 +1,352 @@
+//! Support for the Potree 1.x layout: a `cloud.js` file describing the point cloud,
+//! and a tree of directories holding one `.hrc` hierarchy file per hierarchy chunk
+//! and one `.bin`, `.las` or `.laz` file per node.
+
+use crate::hierarchy::LegacyHierarchyNodeEntry;
+use crate::metadata::{AttributeMetadata, AttributeType, BoundingBox, HierarchyMetadata, Metadata};
+use crate::octree::node::OctreeNode;
+use crate::octree::{FlatOctree, NodeId};
+use crate::point_cloud::{LoadPotreePointCloudError, ReadHierarchyError};
+use binrw::BinReaderExt;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::io::Cursor;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudJs {
+    pub version: String,
+    pub octree_dir: String,
+    #[serde(default)]
+    pub projection: String,
+    #[serde(default)]
+    pub points: u64,
+    pub bounding_box: LegacyBoundingBox,
+    pub tight_bounding_box: Option<LegacyBoundingBox>,
+    pub point_attributes: LegacyPointAttributes,
+    pub spacing: f64,
+    pub scale: f64,
+    pub hierarchy_step_size: u16,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LegacyBoundingBox {
+    pub lx: f64,
+    pub ly: f64,
+    pub lz: f64,
+    pub ux: f64,
+    pub uy: f64,
+    pub uz: f64,
+}
+
+/// Either the list of attributes stored in `.bin` files, or `"LAS"`/`"LAZ"`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum LegacyPointAttributes {
+    Binary(Vec<String>),
+    Las(String),
+}
+
+/// Where the hierarchy and the points of a Potree 1.x point cloud are stored.
+#[derive(Clone, Debug)]
+pub struct LegacyLayout {
+    pub octree_url: String,
+    pub hierarchy_step_size: u16,
+    pub extension: &'static str,
+}
+
+impl LegacyLayout {
+    /// Url of the `.hrc` file holding the hierarchy chunk whose root is `name`.
+    pub fn hierarchy_url(&self, name: &str) -> String {
+        format!(
+            "{}/{}/{}.hrc",
+            self.octree_url,
+            self.hierarchy_path(name),
+            name
+        )
+    }
+
+    /// Url of the file holding the points of the node `name`.
+    pub fn node_url(&self, name: &str) -> String {
+        format!(
+            "{}/{}/{}.{}",
+            self.octree_url,
+            self.hierarchy_path(name),
+            name,
+            self.extension
+        )
+    }
+
+    // Port of `PointCloudOctreeGeometryNode.getHierarchyPath`
+    fn hierarchy_path(&self, name: &str) -> String {
+        let step_size = self.hierarchy_step_size as usize;
+        let indices = &name[1..];
+        let num_parts = indices.len() / step_size;
+
+        let mut path = "r".to_string();
+        for i in 0..num_parts {
+            path.push('/');
+            path.push_str(&indices[i * step_size..(i + 1) * step_size]);
+        }
+
+        path
+    }
+}
+
+impl CloudJs {
+    pub(crate) fn layout(&self, url: &str) -> LegacyLayout {
+        let extension = match &self.point_attributes {
+            LegacyPointAttributes::Las(format) if format == "LAZ" => "laz",
+            LegacyPointAttributes::Las(_) => "las",
+            LegacyPointAttributes::Binary(_) => "bin",
+        };
+
+        LegacyLayout {
+            octree_url: format!("{}/{}", url, self.octree_dir),
+            hierarchy_step_size: self.hierarchy_step_size,
+            extension,
+        }
+    }
+
+    /// Converts the `cloud.js` description to the Potree 2 metadata model.
+    ///
+    /// The encoding is set to `BINARY`, `LAS` or `LAZ`, and the attributes are
+    /// renamed to the names used by Potree 2. The attributes of `.las` and `.laz` nodes
+    /// depend on their point format, only their position is listed here.
+    pub(crate) fn to_metadata(&self) -> Result<Metadata, LoadPotreePointCloudError> {
+        // the nodes are grouped in directories and hierarchy chunks of this many levels
+        if self.hierarchy_step_size == 0 {
+            return Err(LoadPotreePointCloudError::InvalidStepSize(
+                self.hierarchy_step_size,
+            ));
+        }
+
+        let bounding_box = &self.bounding_box;
+
+        let (encoding, attributes) = match &self.point_attributes {
+            LegacyPointAttributes::Binary(names) => (
+                "BINARY".to_string(),
+                names
+                    .iter()
+                    .map(|name| {
+                        legacy_attribute(name).ok_or_else(|| {
+                            LoadPotreePointCloudError::UnsupportedAttribute(name.clone())
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            LegacyPointAttributes::Las(format) => (
+                format.clone(),
+                vec![AttributeMetadata::new("position", AttributeType::Int32, 3)],
+            ),
+        };
+
+        Ok(Metadata {
+            version: self.version.clone(),
+            name: String::new(),
+            description: String::new(),
+            points: self.points,
+            projection: self.projection.clone(),
+            hierarchy: HierarchyMetadata {
+                first_chunk_size: 0,
+                step_size: self.hierarchy_step_size,
+                depth: 0,
+            },
+            offset: [bounding_box.lx, bounding_box.ly, bounding_box.lz],
+            scale: [self.scale; 3],
+            spacing: self.spacing,
+            bounding_box: BoundingBox {
+                min: [bounding_box.lx, bounding_box.ly, bounding_box.lz],
+                max: [bounding_box.ux, bounding_box.uy, bounding_box.uz],
+            },
+            encoding,
+            attributes,
+        })
+    }
+}
+
+// Names, types and sizes from Potree 1.8 `PointAttributes.js`
+fn legacy_attribute(name: &str) -> Option<AttributeMetadata> {
+    let attribute = AttributeMetadata::new;
+
+    let attribute = match name {
+        "POSITION_CARTESIAN" => attribute("position", AttributeType::UInt32, 3),
+        "COLOR_PACKED" | "RGBA_PACKED" => attribute("rgba", AttributeType::UInt8, 4),
+        "RGB_PACKED" => attribute("rgb", AttributeType::UInt8, 3),
+        "COLOR_FLOATS_1" | "COLOR_FLOATS_255" => attribute(name, AttributeType::Float, 3),
+        "NORMAL_FLOATS" | "NORMAL" => attribute("normal", AttributeType::Float, 3),
+        "NORMAL_SPHEREMAPPED" => attribute("normal spheremapped", AttributeType::UInt8, 2),
+        "NORMAL_OCT16" => attribute("normal oct16", AttributeType::UInt8, 2),
+        "FILLER" => attribute("filler", AttributeType::UInt8, 1),
+        "INTENSITY" => attribute("intensity", AttributeType::UInt16, 1),
+        "CLASSIFICATION" => attribute("classification", AttributeType::UInt8, 1),
+        "RETURN_NUMBER" => attribute("return number", AttributeType::UInt8, 1),
+        "NUMBER_OF_RETURNS" => attribute("number of returns", AttributeType::UInt8, 1),
+        "SOURCE_ID" => attribute("point source id", AttributeType::UInt16, 1),
+        "INDICES" => attribute("indices", AttributeType::UInt32, 1),
+        "SPACING" => attribute("spacing", AttributeType::Float, 1),
+        "GPS_TIME" => attribute("gps-time", AttributeType::Double, 1),
+        _ => return None,
+    };
+
+    Some(attribute)
+}
+
+/// Parses a `.hrc` file, whose first entry is the node `node_id`.
+///
+/// Entries are stored breadth first, each one made of a child mask and a point count.
+/// Nodes at `step_size` levels below the chunk root have their children stored in their
+/// own `.hrc` file and are marked as proxies.
+pub(crate) fn parse_hierarchy(
+    octree: &mut FlatOctree<OctreeNode>,
+    node_id: NodeId,
+    buf: &[u8],
+    step_size: u16,
+) -> Result<(), ReadHierarchyError> {
+    const BYTES_PER_NODE: usize = 5;
+    let chunk_root = octree
+        .node(node_id)
+        .ok_or(ReadHierarchyError::NodeNotFound)?;
+    let (chunk_name, chunk_level) = (chunk_root.name.clone(), chunk_root.level);
+
+    if buf.is_empty() || !buf.len().is_multiple_of(BYTES_PER_NODE) {
+        return Err(ReadHierarchyError::InvalidHierarchySize {
+            node: chunk_name,
+            offset: 0,
+            size: buf.len(),
+        });
+    }
+
+    let mut cursor = Cursor::new(buf);
+    let num_entries = buf.len() / BYTES_PER_NODE;
+    let entries = (0..num_entries)
+        .map(|_| cursor.read_le())
+        .collect::<Result<Vec<LegacyHierarchyNodeEntry>, _>>()?;
+
+    // a node has children in this chunk if it is not a leaf and not at the chunk's last level
+    let has_children = |entry: &LegacyHierarchyNodeEntry, level: u32| {
+        entry.child_mask != 0 && level - chunk_level < step_size as u32
+    };
+
+    // check that every child announced by a child mask has an entry,
+    // before modifying the octree
+    let mut levels = VecDeque::from([chunk_level]);
+    let mut num_nodes = 1;
+    for (i, entry) in entries.iter().enumerate() {
+        let Some(level) = levels.pop_front() else {
+            // remaining entries are not referenced by any node
+            break;
+        };
+        if !has_children(entry, level) {
+            continue;
+        }
+
+        let num_children = entry.child_mask.count_ones() as usize;
+        num_nodes += num_children;
+        if num_nodes > num_entries {
+            return Err(ReadHierarchyError::InvalidChildMask {
+                node: chunk_name,
+                entry: i,
+                required: num_nodes,
+                available: num_entries,
+            });
+        }
+        levels.extend(std::iter::repeat_n(level + 1, num_children));
+    }
+
+    // nodes whose entry has been read, waiting for their children to be created
+    let mut queue = VecDeque::from([node_id]);
+    let mut entries = entries.into_iter();
+
+    while let Some(current_id) = queue.pop_front() {
+        let header = entries.next().expect("entries checked above");
+        let current = octree.node_mut(current_id).unwrap();
+        current.num_points = header.num_points;
+
+        if header.child_mask == 0 {
+            current.node_type = 1;
+            continue;
+        }
+
+        if !has_children(&header, current.level) {
+            // children are described in the node's own hierarchy file
+            current.node_type = 2;
+            continue;
+        }
+
+        current.node_type = 0;
+
+        // clone/copy just what we need
+        let (current_name, current_bounding_box, current_spacing, current_level) = (
+            current.name.clone(),
+            current.bounding_box.clone(),
+            current.spacing,
+            current.level,
+        );
+
+        let mut children = Vec::with_capacity(8);
+
+        for child_index in 0..8 {
+            let child_exists = ((1 << child_index) & header.child_mask) != 0;
+            if !child_exists {
+                continue;
+            }
+
+            let child_id = octree.insert(OctreeNode {
+                name: format!("{}{}", current_name, child_index),
+                bounding_box: current_bounding_box.child(child_index),
+                spacing: current_spacing / 2.0,
+                level: current_level + 1,
+                parent: Some(current_id),
+                ..Default::default()
+            });
+            octree.node_mut(child_id).unwrap().id = Some(child_id);
+
+            children.push(child_id);
+            queue.push_back(child_id);
+        }
+
+        octree.node_mut(current_id).unwrap().children = children;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cloud_js(hierarchy_step_size: u16) -> CloudJs {
+        serde_json::from_value(serde_json::json!({
+            "version": "1.8",
+            "octreeDir": "data",
+            "boundingBox": { "lx": 0, "ly": 0, "lz": 0, "ux": 1, "uy": 1, "uz": 1 },
+            "pointAttributes": ["POSITION_CARTESIAN", "RGB_PACKED"],
+            "spacing": 0.1,
+            "scale": 0.001,
+            "hierarchyStepSize": hierarchy_step_size,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn to_metadata_rejects_zero_step_size() {
+        assert!(cloud_js(5).to_metadata().is_ok());
+        assert!(matches!(
+            cloud_js(0).to_metadata(),
+            Err(LoadPotreePointCloudError::InvalidStepSize(0))
+        ));
+    }
+
+    #[test]
+    fn hierarchy_path_groups_levels() {
+        let layout = cloud_js(2).layout("http://host");
+
+        assert_eq!(layout.node_url("r"), "http://host/data/r/r.bin");
+        assert_eq!(layout.node_url("r053"), "http://host/data/r/05/r053.bin");
+        assert_eq!(
+            layout.hierarchy_url("r0536"),
+            "http://host/data/r/05/36/r0536.hrc"
+        );
+    }
+}
@@ -6,3 +6,16 @@ pub mod prelude;
 pub mod point_cloud;
 pub mod octree;
 pub mod point;
+pub mod legacy;
+pub mod cache;
+pub mod query;
+pub mod pick;
+pub mod profile;
+pub mod writer;
+#[cfg(feature = "fs")]
+pub mod converter;
+pub mod export;
+#[cfg(feature = "las")]
+pub mod import;
+mod decoder;
+mod encoder;
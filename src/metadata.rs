@@ -1,8 +1,8 @@
 use crate::octree::aabb::Aabb;
 use crate::octree::node::OctreeNode;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Metadata {
     pub version: String,
@@ -19,7 +19,7 @@ pub struct Metadata {
     pub attributes: Vec<AttributeMetadata>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct HierarchyMetadata {
     pub first_chunk_size: u64,
@@ -27,14 +27,14 @@ pub struct HierarchyMetadata {
     pub depth: u16,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct BoundingBox {
     pub min: [f64; 3],
     pub max: [f64; 3],
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AttributeType {
     #[serde(rename = "int8")]
     Int8,
@@ -59,7 +59,7 @@ pub enum AttributeType {
     #[serde(rename = "undefined")]
     Undefined,
 }
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct AttributeMetadata {
     pub name: String,
@@ -72,6 +72,35 @@ pub struct AttributeMetadata {
     pub max: Vec<f32>,
 }
 
+impl AttributeType {
+    /// Size in bytes of one element of this type.
+    pub fn element_size(&self) -> u16 {
+        match self {
+            AttributeType::Int8 | AttributeType::UInt8 | AttributeType::Undefined => 1,
+            AttributeType::Int16 | AttributeType::UInt16 => 2,
+            AttributeType::Int32 | AttributeType::UInt32 | AttributeType::Float => 4,
+            AttributeType::Int64 | AttributeType::UInt64 | AttributeType::Double => 8,
+        }
+    }
+}
+
+impl AttributeMetadata {
+    pub fn new(name: &str, r#type: AttributeType, num_elements: u16) -> Self {
+        let element_size = r#type.element_size();
+
+        Self {
+            name: name.to_string(),
+            description: String::new(),
+            size: element_size * num_elements,
+            num_elements,
+            element_size,
+            r#type,
+            min: Vec::new(),
+            max: Vec::new(),
+        }
+    }
+}
+
 impl Metadata {
 
     pub(crate) fn create_root_node(&self) -> OctreeNode {
@@ -86,11 +115,11 @@ impl Metadata {
     }
 }
 
-impl Into<Aabb> for BoundingBox {
-    fn into(self) -> Aabb {
+impl From<BoundingBox> for Aabb {
+    fn from(bounding_box: BoundingBox) -> Self {
         Aabb {
-            min: self.min.into(),
-            max: self.max.into(),
+            min: bounding_box.min.into(),
+            max: bounding_box.max.into(),
         }
     }
 }
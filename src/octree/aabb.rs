@@ -1,6 +1,8 @@
-use glam::DVec3;
+use super::frustum::Frustum;
+use glam::{DMat4, DVec3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Aabb {
     pub min: DVec3,
     pub max: DVec3,
@@ -10,28 +12,232 @@ impl Aabb {
     pub fn new(min: DVec3, max: DVec3) -> Self {
         Self { min, max }
     }
+
+    /// The smallest box holding all the points, `None` if there are none.
+    pub fn from_points<I: IntoIterator<Item = DVec3>>(points: I) -> Option<Self> {
+        points.into_iter().fold(None, |aabb: Option<Aabb>, point| {
+            Some(match aabb {
+                Some(aabb) => Aabb::new(aabb.min.min(point), aabb.max.max(point)),
+                None => Aabb::new(point, point),
+            })
+        })
+    }
+
+    pub fn center(&self) -> DVec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> DVec3 {
+        self.max - self.min
+    }
+
+    /// Radius of the bounding sphere.
+    pub fn radius(&self) -> f64 {
+        self.size().length() * 0.5
+    }
+
+    /// The eight corners, indexed like the children.
+    pub fn corners(&self) -> [DVec3; 8] {
+        std::array::from_fn(|index| {
+            DVec3::new(
+                if index & 0b0100 > 0 {
+                    self.max.x
+                } else {
+                    self.min.x
+                },
+                if index & 0b0010 > 0 {
+                    self.max.y
+                } else {
+                    self.min.y
+                },
+                if index & 0b0001 > 0 {
+                    self.max.z
+                } else {
+                    self.min.z
+                },
+            )
+        })
+    }
+
+    /// Returns true if the point is inside the box, boundary included.
+    pub fn contains_point(&self, point: DVec3) -> bool {
+        self.min.cmple(point).all() && self.max.cmpge(point).all()
+    }
+
+    /// Returns true if the boxes overlap, touching boxes included.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+
+    /// Returns true if the sphere overlaps the box.
+    pub fn intersects_sphere(&self, center: DVec3, radius: f64) -> bool {
+        self.distance_squared_to_point(center) <= radius * radius
+    }
+
+    /// Returns true if the box is at least partially inside the frustum.
+    pub fn intersects_frustum(&self, frustum: &Frustum) -> bool {
+        frustum.intersects_aabb(self)
+    }
+
+    /// The smallest box holding both boxes.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// The box grown by `margin` on every side.
+    pub fn expand(&self, margin: f64) -> Aabb {
+        Aabb::new(
+            self.min - DVec3::splat(margin),
+            self.max + DVec3::splat(margin),
+        )
+    }
+
+    /// The point of the box closest to `point`, `point` itself if it is inside.
+    pub fn closest_point(&self, point: DVec3) -> DVec3 {
+        point.clamp(self.min, self.max)
+    }
+
+    /// Distance from the point to the box, 0 if it is inside.
+    pub fn distance_to_point(&self, point: DVec3) -> f64 {
+        self.distance_squared_to_point(point).sqrt()
+    }
+
+    pub fn distance_squared_to_point(&self, point: DVec3) -> f64 {
+        self.closest_point(point).distance_squared(point)
+    }
+
+    /// Distances along the ray, in multiples of `direction`, where it enters and leaves the box,
+    /// or `None` if it misses the box. The entry distance is 0 if `origin` is inside the box.
+    pub fn intersect_ray(&self, origin: DVec3, direction: DVec3) -> Option<(f64, f64)> {
+        let inv_direction = direction.recip();
+        let t1 = (self.min - origin) * inv_direction;
+        let t2 = (self.max - origin) * inv_direction;
+
+        // 0 * inf is NaN on the axes parallel to the ray whose origin lies on a face,
+        // these axes don't bound the ray
+        let on_face = t1.is_nan_mask() | t2.is_nan_mask();
+        let t_near = DVec3::select(on_face, DVec3::NEG_INFINITY, t1.min(t2))
+            .max_element()
+            .max(0.0);
+        let t_far = DVec3::select(on_face, DVec3::INFINITY, t1.max(t2)).min_element();
+
+        (t_near <= t_far).then_some((t_near, t_far))
+    }
+
+    /// The box holding the eight corners transformed by `matrix`, which must be affine:
+    /// no perspective divide is applied.
+    pub fn transform(&self, matrix: &DMat4) -> Aabb {
+        Aabb::from_points(self.corners().map(|corner| matrix.transform_point3(corner)))
+            .expect("a box has corners")
+    }
+
+    /// The box of the child at `index` in the octree, `index` being `0bxyz`.
+    pub fn child(&self, index: usize) -> Aabb {
+        let mut min = self.min;
+        let mut max = self.max;
+        let size = (max - min) * 0.5;
+
+        if (index & 0b0001) > 0 {
+            min.z += size.z;
+        } else {
+            max.z -= size.z;
+        }
+        if (index & 0b0010) > 0 {
+            min.y += size.y;
+        } else {
+            max.y -= size.y;
+        }
+        if (index & 0b0100) > 0 {
+            min.x += size.x;
+        } else {
+            max.x -= size.x;
+        }
+
+        Aabb::new(min, max)
+    }
+
+    /// The boxes of the eight children, in child index order.
+    pub fn children(&self) -> [Aabb; 8] {
+        std::array::from_fn(|index| self.child(index))
+    }
 }
 
+/// Same as [`Aabb::child`].
 pub fn create_child_aabb(aabb: &Aabb, index: usize) -> Aabb {
-    let mut min = aabb.min;
-    let mut max = aabb.max;
-    let size = (max - min) * 0.5;
+    aabb.child(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if (index & 0b0001) > 0 {
-        min.z += size.z;
-    } else {
-        max.z -= size.z;
+    fn unit_box() -> Aabb {
+        Aabb::new(DVec3::ZERO, DVec3::ONE)
     }
-    if (index & 0b0010) > 0 {
-        min.y += size.y;
-    } else {
-        max.y -= size.y;
+
+    #[test]
+    fn transform_affine() {
+        let matrix = DMat4::from_translation(DVec3::new(10.0, 0.0, 0.0))
+            * DMat4::from_rotation_z(std::f64::consts::FRAC_PI_2)
+            * DMat4::from_scale(DVec3::new(2.0, 1.0, 1.0));
+
+        let transformed = unit_box().transform(&matrix);
+
+        assert!(
+            transformed
+                .min
+                .abs_diff_eq(DVec3::new(9.0, 0.0, 0.0), 1e-12)
+        );
+        assert!(
+            transformed
+                .max
+                .abs_diff_eq(DVec3::new(10.0, 2.0, 1.0), 1e-12)
+        );
     }
-    if (index & 0b0100) > 0 {
-        min.x += size.x;
-    } else {
-        max.x -= size.x;
+
+    #[test]
+    fn intersect_ray_through_box() {
+        let hit = unit_box().intersect_ray(DVec3::new(-1.0, 0.5, 0.5), DVec3::new(2.0, 0.0, 0.0));
+
+        assert_eq!(hit, Some((0.5, 1.0)));
     }
 
-    Aabb::new(min, max)
+    #[test]
+    fn intersect_ray_parallel_to_slab() {
+        let aabb = unit_box();
+
+        // inside the y and z slabs
+        assert_eq!(
+            aabb.intersect_ray(DVec3::new(-1.0, 0.5, 0.5), DVec3::X),
+            Some((1.0, 2.0))
+        );
+        // outside the y slab
+        assert_eq!(
+            aabb.intersect_ray(DVec3::new(-1.0, 2.0, 0.5), DVec3::X),
+            None
+        );
+        // on the faces of the y and z slabs
+        assert_eq!(
+            aabb.intersect_ray(DVec3::new(-1.0, 0.0, 1.0), DVec3::X),
+            Some((1.0, 2.0))
+        );
+    }
+
+    #[test]
+    fn intersect_ray_from_inside() {
+        let hit = unit_box().intersect_ray(DVec3::splat(0.5), DVec3::new(0.0, 0.0, -1.0));
+
+        assert_eq!(hit, Some((0.0, 0.5)));
+    }
+
+    #[test]
+    fn intersect_ray_behind_origin() {
+        let aabb = unit_box();
+
+        assert_eq!(
+            aabb.intersect_ray(DVec3::new(2.0, 0.5, 0.5), DVec3::X),
+            None
+        );
+        assert_eq!(aabb.intersect_ray(DVec3::splat(3.0), DVec3::ONE), None);
+    }
 }
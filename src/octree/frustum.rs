@@ -0,0 +1,93 @@
+use super::aabb::Aabb;
+use super::node::OctreeNode;
+use super::{FlatOctree, NodeId};
+use glam::{DMat4, DVec3, DVec4};
+
+/// A view frustum, defined by six planes whose normals point inside.
+#[derive(Clone, Debug)]
+pub struct Frustum {
+    pub planes: [DVec4; 6],
+}
+
+/// Result of culling an octree against a frustum.
+#[derive(Clone, Debug, Default)]
+pub struct FrustumCulling {
+    /// Nodes whose bounding box intersects the frustum, parents first.
+    pub visible: Vec<NodeId>,
+    /// Visible nodes whose hierarchy is not loaded yet (`node_type == 2`).
+    /// Their children will be known once `PotreePointCloud::load_hierarchy` is called.
+    pub proxies: Vec<NodeId>,
+}
+
+impl Frustum {
+    /// Extract the frustum planes from a view-projection matrix (Gribb & Hartmann).
+    ///
+    /// The near plane is extracted for a `[-1, 1]` depth range, which is conservative
+    /// for projections using a `[0, 1]` depth range.
+    pub fn from_view_projection(view_projection: DMat4) -> Self {
+        let row0 = view_projection.row(0);
+        let row1 = view_projection.row(1);
+        let row2 = view_projection.row(2);
+        let row3 = view_projection.row(3);
+
+        let planes = [
+            row3 + row0, // left
+            row3 - row0, // right
+            row3 + row1, // bottom
+            row3 - row1, // top
+            row3 + row2, // near
+            row3 - row2, // far
+        ]
+        .map(|plane| plane / plane.truncate().length());
+
+        Self { planes }
+    }
+
+    /// Returns true if the box is at least partially inside the frustum.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner of the box the furthest along the plane normal
+            let positive = DVec3::select(plane.truncate().cmpge(DVec3::ZERO), aabb.max, aabb.min);
+
+            plane.truncate().dot(positive) + plane.w >= 0.0
+        })
+    }
+
+    /// Returns true if the point is inside the frustum.
+    pub fn contains_point(&self, point: DVec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+}
+
+impl FlatOctree<OctreeNode> {
+    /// Walk the loaded octree and return the nodes intersecting the frustum of
+    /// the provided view-projection matrix.
+    pub fn frustum_cull(&self, view_projection: DMat4) -> FrustumCulling {
+        let frustum = Frustum::from_view_projection(view_projection);
+        let mut culling = FrustumCulling::default();
+
+        let mut stack = vec![self.root_id()];
+
+        while let Some(node_id) = stack.pop() {
+            let node = self
+                .node(node_id)
+                .expect("missing node in hierarchy, shouldn't happen");
+
+            if !frustum.intersects_aabb(&node.bounding_box) {
+                continue;
+            }
+
+            culling.visible.push(node_id);
+            if node.node_type == 2 {
+                culling.proxies.push(node_id);
+            }
+
+            // push in reverse order to visit children in order
+            stack.extend(node.children.iter().rev());
+        }
+
+        culling
+    }
+}
@@ -0,0 +1,222 @@
+use super::aabb::Aabb;
+use super::node::OctreeNode;
+use std::fmt::Display;
+
+/// Position of a node in the octree: its level and its cell on the grid of that level.
+///
+/// At level `n`, the grid has `2^n` cells along each axis. The root is at level 0, cell `(0, 0, 0)`.
+/// A key maps one to one to a Potree node name: `r` followed by one child index per level,
+/// each index holding the x, y and z bits, in that order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OctreeKey {
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+impl OctreeKey {
+    /// Deepest level whose cells can be addressed with `u32` coordinates.
+    pub const MAX_LEVEL: u32 = 32;
+
+    pub const ROOT: OctreeKey = OctreeKey {
+        level: 0,
+        x: 0,
+        y: 0,
+        z: 0,
+    };
+
+    pub fn new(level: u32, x: u32, y: u32, z: u32) -> Self {
+        Self { level, x, y, z }
+    }
+
+    /// Parses a Potree node name such as `r0413`.
+    ///
+    /// Returns `None` if the name does not start with `r`, contains something else
+    /// than digits from 0 to 7, or is deeper than [`Self::MAX_LEVEL`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        let indices = name.strip_prefix('r')?;
+        if indices.len() > Self::MAX_LEVEL as usize {
+            return None;
+        }
+
+        indices.chars().try_fold(Self::ROOT, |key, c| {
+            let index = c.to_digit(8)?;
+            Some(key.child(index as usize))
+        })
+    }
+
+    /// The Potree node name of the key, such as `r0413`.
+    pub fn to_name(&self) -> String {
+        let mut name = String::with_capacity(self.level as usize + 1);
+        name.push('r');
+
+        for level in (0..self.level).rev() {
+            let index = (((self.x >> level) & 1) << 2)
+                | (((self.y >> level) & 1) << 1)
+                | ((self.z >> level) & 1);
+            name.push(char::from(b'0' + index as u8));
+        }
+
+        name
+    }
+
+    /// Index of the node among its parent's children, `None` for the root.
+    pub fn child_index(&self) -> Option<usize> {
+        if self.level == 0 {
+            return None;
+        }
+
+        Some((((self.x & 1) << 2) | ((self.y & 1) << 1) | (self.z & 1)) as usize)
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        if self.level == 0 {
+            return None;
+        }
+
+        Some(Self::new(
+            self.level - 1,
+            self.x >> 1,
+            self.y >> 1,
+            self.z >> 1,
+        ))
+    }
+
+    /// The child at `index`, using the same indices as the hierarchy child masks.
+    pub fn child(&self, index: usize) -> Self {
+        Self::new(
+            self.level + 1,
+            (self.x << 1) | ((index as u32 >> 2) & 1),
+            (self.y << 1) | ((index as u32 >> 1) & 1),
+            (self.z << 1) | (index as u32 & 1),
+        )
+    }
+
+    pub fn children(&self) -> [Self; 8] {
+        std::array::from_fn(|index| self.child(index))
+    }
+
+    /// The node at the same level, offset by the provided number of cells.
+    ///
+    /// Returns `None` if the neighbour is outside of the octree.
+    pub fn neighbor(&self, dx: i64, dy: i64, dz: i64) -> Option<Self> {
+        let cells = 1_i64 << self.level;
+        let offset = |value: u32, delta: i64| {
+            let value = value as i64 + delta;
+            (0..cells).contains(&value).then_some(value as u32)
+        };
+
+        Some(Self::new(
+            self.level,
+            offset(self.x, dx)?,
+            offset(self.y, dy)?,
+            offset(self.z, dz)?,
+        ))
+    }
+
+    /// The nodes sharing a face, an edge or a corner with this one, at the same level.
+    pub fn neighbors(&self) -> Vec<Self> {
+        let mut neighbors = Vec::with_capacity(26);
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if (dx, dy, dz) == (0, 0, 0) {
+                        continue;
+                    }
+                    if let Some(neighbor) = self.neighbor(dx, dy, dz) {
+                        neighbors.push(neighbor);
+                    }
+                }
+            }
+        }
+
+        neighbors
+    }
+
+    /// Returns true if `other` is this node or one of its descendants.
+    pub fn contains(&self, other: &OctreeKey) -> bool {
+        if other.level < self.level {
+            return false;
+        }
+
+        // a u32 can't be shifted by 32, the root is the only node that far up
+        let shift = other.level - self.level;
+        let ancestor = |value: u32| value.checked_shr(shift).unwrap_or(0);
+
+        ancestor(other.x) == self.x && ancestor(other.y) == self.y && ancestor(other.z) == self.z
+    }
+
+    /// Bounding box of the node, given the bounding box of the root node.
+    pub fn bounding_box(&self, root: &Aabb) -> Aabb {
+        let name = self.to_name();
+
+        name[1..].bytes().fold(root.clone(), |aabb, index| {
+            aabb.child((index - b'0') as usize)
+        })
+    }
+}
+
+impl Display for OctreeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_name())
+    }
+}
+
+impl OctreeNode {
+    /// The key of the node, parsed from its name.
+    pub fn key(&self) -> Option<OctreeKey> {
+        OctreeKey::from_name(&self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_round_trip() {
+        for name in ["r", "r0", "r7", "r0413", "r76543210"] {
+            let key = OctreeKey::from_name(name).unwrap();
+
+            assert_eq!(key.level as usize, name.len() - 1);
+            assert_eq!(key.to_name(), name);
+        }
+
+        // x, y and z bits, in that order
+        assert_eq!(
+            OctreeKey::from_name("r46"),
+            Some(OctreeKey::new(2, 3, 1, 0))
+        );
+        assert_eq!(OctreeKey::new(2, 3, 1, 0).to_name(), "r46");
+    }
+
+    #[test]
+    fn from_name_rejects_invalid_names() {
+        for name in ["", "0", "x0", "r8", "r09", "r0a", "r-1", "R0"] {
+            assert_eq!(OctreeKey::from_name(name), None, "{name}");
+        }
+
+        let deepest = format!("r{}", "7".repeat(OctreeKey::MAX_LEVEL as usize));
+        assert!(OctreeKey::from_name(&deepest).is_some());
+        assert_eq!(OctreeKey::from_name(&format!("{deepest}7")), None);
+    }
+
+    #[test]
+    fn child_and_parent() {
+        let key = OctreeKey::from_name("r04").unwrap();
+
+        assert_eq!(OctreeKey::ROOT.parent(), None);
+        assert_eq!(OctreeKey::ROOT.child_index(), None);
+        for (index, child) in key.children().into_iter().enumerate() {
+            assert_eq!(child.to_name(), format!("r04{index}"));
+            assert_eq!(child.child_index(), Some(index));
+            assert_eq!(child.parent(), Some(key));
+            assert!(key.contains(&child));
+            assert!(!child.contains(&key));
+        }
+        assert!(OctreeKey::ROOT.contains(&key));
+        assert!(!key.contains(&OctreeKey::from_name("r05").unwrap()));
+    }
+}
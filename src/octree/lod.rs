@@ -0,0 +1,160 @@
+use super::frustum::Frustum;
+use super::node::OctreeNode;
+use super::{FlatOctree, NodeId};
+use glam::{DMat4, DVec3};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Camera and budget used to select the nodes to render.
+#[derive(Clone, Debug)]
+pub struct LodOptions {
+    pub view_projection: DMat4,
+    pub camera_position: DVec3,
+    /// Vertical field of view, in radians.
+    pub fov_y: f64,
+    /// Height of the viewport, in pixels.
+    pub screen_height: f64,
+    /// Maximum number of points of the selected nodes.
+    pub point_budget: u64,
+    /// Nodes whose bounding sphere is smaller than this on screen, in pixels, are skipped.
+    pub min_node_pixel_size: f64,
+    /// Children of a node are skipped once its point spacing is smaller than this on screen, in pixels.
+    pub max_screen_space_error: f64,
+}
+
+impl Default for LodOptions {
+    fn default() -> Self {
+        Self {
+            view_projection: DMat4::IDENTITY,
+            camera_position: DVec3::ZERO,
+            fov_y: 60_f64.to_radians(),
+            screen_height: 1080.0,
+            point_budget: 1_000_000,
+            min_node_pixel_size: 30.0,
+            max_screen_space_error: 1.0,
+        }
+    }
+}
+
+/// Nodes selected for rendering.
+#[derive(Clone, Debug, Default)]
+pub struct LodSelection {
+    /// Selected nodes, most important first.
+    pub nodes: Vec<NodeId>,
+    /// Selected nodes whose hierarchy is not loaded yet (`node_type == 2`).
+    /// Their children may be selected once `PotreePointCloud::load_hierarchy` is called.
+    pub proxies: Vec<NodeId>,
+    /// Total number of points of the selected nodes.
+    pub num_points: u64,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    node_id: NodeId,
+    weight: f64,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.weight.total_cmp(&other.weight) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.weight.total_cmp(&other.weight)
+    }
+}
+
+impl FlatOctree<OctreeNode> {
+    /// Select the nodes to load and render, following Potree's visibility algorithm.
+    ///
+    /// Visible nodes are visited by decreasing projected size, until the point budget is reached.
+    pub fn select_nodes(&self, options: &LodOptions) -> LodSelection {
+        let frustum = Frustum::from_view_projection(options.view_projection);
+        let projection_factor = 0.5 * options.screen_height / (options.fov_y * 0.5).tan();
+
+        let mut selection = LodSelection::default();
+        let mut queue = BinaryHeap::new();
+        queue.push(Candidate {
+            node_id: self.root_id(),
+            weight: f64::INFINITY,
+        });
+
+        while let Some(Candidate { node_id, .. }) = queue.pop() {
+            let node = self
+                .node(node_id)
+                .expect("missing node in hierarchy, shouldn't happen");
+
+            if selection.num_points + node.num_points as u64 > options.point_budget {
+                break;
+            }
+
+            if !frustum.intersects_aabb(&node.bounding_box) {
+                continue;
+            }
+
+            selection.nodes.push(node_id);
+            selection.num_points += node.num_points as u64;
+
+            if node.node_type == 2 {
+                selection.proxies.push(node_id);
+                continue;
+            }
+
+            // stop refining once the points of this node are dense enough on screen
+            let distance = node_distance(node, options.camera_position);
+            if distance > 0.0
+                && node.spacing * projection_factor / distance <= options.max_screen_space_error
+            {
+                continue;
+            }
+
+            for child_id in &node.children {
+                let child = self
+                    .node(*child_id)
+                    .expect("missing node in hierarchy, shouldn't happen");
+
+                let radius = child.bounding_box.radius();
+                let distance = child
+                    .bounding_box
+                    .center()
+                    .distance(options.camera_position);
+                let screen_pixel_radius = radius * projection_factor / distance;
+
+                if screen_pixel_radius < options.min_node_pixel_size {
+                    continue;
+                }
+
+                // the camera is inside the node's bounding sphere
+                let weight = if distance - radius < 0.0 {
+                    f64::INFINITY
+                } else {
+                    screen_pixel_radius
+                };
+
+                queue.push(Candidate {
+                    node_id: *child_id,
+                    weight,
+                });
+            }
+        }
+
+        selection
+    }
+}
+
+// Distance from the camera to the node's bounding sphere, 0 if the camera is inside
+fn node_distance(node: &OctreeNode, camera_position: DVec3) -> f64 {
+    let distance = node.bounding_box.center().distance(camera_position);
+
+    (distance - node.bounding_box.radius()).max(0.0)
+}
@@ -1,40 +1,54 @@
 pub mod node;
 pub mod aabb;
 pub mod snapshot;
+pub mod frustum;
+pub mod lod;
+pub mod key;
+pub mod traversal;
+pub mod region;
 
 pub mod point_attributes;
 
 use std::fmt::Display;
 
+use serde::{Deserialize, Serialize};
 use slab::Slab;
 
-#[derive(Clone, Debug, Copy, Default)]
-pub struct NodeId(pub(crate) usize);
+/// Identifies a node of a [`FlatOctree`].
+///
+/// Slots of removed nodes are reused, so an id also holds the generation of its slot:
+/// the id of a removed node never resolves to the node inserted in its place.
+///
+/// An id is displayed as the index of its slot only, the generation shows in `Debug`.
+#[derive(Clone, Debug, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NodeId {
+    pub(crate) index: usize,
+    pub(crate) generation: u32,
+}
 
 impl Display for NodeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        write!(f, "{}", self.index)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct FlatOctree<T> {
     storage: Slab<T>,
+    // Current generation of each slot of the storage
+    generations: Vec<u32>,
     root_id: NodeId,
 }
-impl<T> FlatOctree<T>
-where
-    T: Default,
-{
+impl<T> FlatOctree<T> {
     pub fn root(&self) -> &T {
         self.storage
-            .get(self.root_id.0)
+            .get(self.root_id.index)
             .expect("root node not found - invariant broken")
     }
 
     pub fn root_mut(&mut self) -> &mut T {
         self.storage
-            .get_mut(self.root_id.0)
+            .get_mut(self.root_id.index)
             .expect("root node not found - invariant broken")
     }
 
@@ -42,12 +56,24 @@ where
         self.root_id
     }
 
+    /// Returns true if `node_id` refers to a node which has not been removed.
+    pub fn contains(&self, node_id: NodeId) -> bool {
+        self.generations.get(node_id.index) == Some(&node_id.generation)
+            && self.storage.contains(node_id.index)
+    }
+
     pub fn node(&self, node_id: NodeId) -> Option<&T> {
-        self.storage.get(node_id.0)
+        if !self.contains(node_id) {
+            return None;
+        }
+        self.storage.get(node_id.index)
     }
 
     pub fn node_mut(&mut self, node_id: NodeId) -> Option<&mut T> {
-        self.storage.get_mut(node_id.0)
+        if !self.contains(node_id) {
+            return None;
+        }
+        self.storage.get_mut(node_id.index)
     }
 
     pub fn reserve(&mut self, additional: usize) {
@@ -55,7 +81,37 @@ where
     }
 
     pub fn insert(&mut self, node: T) -> NodeId {
-        NodeId(self.storage.insert(node))
+        let index = self.storage.insert(node);
+        if index == self.generations.len() {
+            self.generations.push(0);
+        }
+
+        NodeId {
+            index,
+            generation: self.generations[index],
+        }
+    }
+
+    /// Removes a single node and returns it, without updating its parent or its children.
+    ///
+    /// The ids of the removed node become invalid. The root node can't be removed.
+    pub fn remove(&mut self, node_id: NodeId) -> Option<T> {
+        if node_id == self.root_id || !self.contains(node_id) {
+            return None;
+        }
+
+        self.generations[node_id.index] = self.generations[node_id.index].wrapping_add(1);
+        self.storage.try_remove(node_id.index)
+    }
+
+    /// Number of nodes stored, including the root.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Returns true if no node is stored, which never happens as the root can't be removed.
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty()
     }
 }
 
@@ -64,11 +120,22 @@ where
     T: Default,
 {
     pub fn new() -> Self {
-        let mut storage = Slab::new();
+        let mut this = Self {
+            storage: Slab::new(),
+            generations: Vec::new(),
+            root_id: NodeId::default(),
+        };
+        this.root_id = this.insert(T::default());
 
-        let root_node = T::default();
-        let root_id = NodeId(storage.insert(root_node));
+        this
+    }
+}
 
-        Self { storage, root_id }
+impl<T> Default for FlatOctree<T>
+where
+    T: Default,
+{
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,141 @@
+use super::aabb::Aabb;
+use super::frustum::Frustum;
+use glam::{DVec2, DVec3};
+
+/// A region of space, used to query the points of a point cloud.
+pub trait Region {
+    /// Returns true if the box may hold points inside the region.
+    /// False positives are allowed, they only cost loading more points.
+    fn intersects_aabb(&self, aabb: &Aabb) -> bool;
+
+    fn contains_point(&self, point: DVec3) -> bool;
+}
+
+/// A sphere, defined by its center and its radius.
+#[derive(Clone, Debug)]
+pub struct Sphere {
+    pub center: DVec3,
+    pub radius: f64,
+}
+
+/// A polygon on the XY plane, extruded between two heights.
+///
+/// The polygon may be concave, its vertices are listed in any order and it is closed implicitly.
+#[derive(Clone, Debug)]
+pub struct PolygonPrism {
+    pub polygon: Vec<DVec2>,
+    pub min_z: f64,
+    pub max_z: f64,
+}
+
+impl Sphere {
+    pub fn new(center: DVec3, radius: f64) -> Self {
+        Self { center, radius }
+    }
+}
+
+impl PolygonPrism {
+    pub fn new(polygon: Vec<DVec2>, min_z: f64, max_z: f64) -> Self {
+        Self {
+            polygon,
+            min_z,
+            max_z,
+        }
+    }
+
+    // Even-odd rule
+    fn polygon_contains(&self, point: DVec2) -> bool {
+        let mut inside = false;
+        let mut previous = match self.polygon.last() {
+            Some(previous) => *previous,
+            None => return false,
+        };
+
+        for current in &self.polygon {
+            if (current.y > point.y) != (previous.y > point.y) {
+                let x = (previous.x - current.x) * (point.y - current.y) / (previous.y - current.y)
+                    + current.x;
+                if point.x < x {
+                    inside = !inside;
+                }
+            }
+            previous = *current;
+        }
+
+        inside
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (DVec2, DVec2)> + '_ {
+        self.polygon
+            .iter()
+            .zip(self.polygon.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
+    }
+}
+
+impl Region for Aabb {
+    fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.intersects(aabb)
+    }
+
+    fn contains_point(&self, point: DVec3) -> bool {
+        Aabb::contains_point(self, point)
+    }
+}
+
+impl Region for Sphere {
+    fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        aabb.intersects_sphere(self.center, self.radius)
+    }
+
+    fn contains_point(&self, point: DVec3) -> bool {
+        point.distance_squared(self.center) <= self.radius * self.radius
+    }
+}
+
+impl Region for PolygonPrism {
+    fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        if self.polygon.is_empty() || self.min_z > aabb.max.z || self.max_z < aabb.min.z {
+            return false;
+        }
+
+        let (min, max) = (aabb.min.truncate(), aabb.max.truncate());
+        let corners = [min, DVec2::new(max.x, min.y), max, DVec2::new(min.x, max.y)];
+        let in_rectangle = |point: &DVec2| point.cmpge(min).all() && point.cmple(max).all();
+
+        // a vertex of the polygon is in the box, or a corner of the box is in the polygon
+        if self.polygon.iter().any(in_rectangle)
+            || corners.iter().any(|corner| self.polygon_contains(*corner))
+        {
+            return true;
+        }
+
+        // otherwise, they intersect only if an edge of the polygon crosses the box
+        self.edges().any(|(a, b)| {
+            (0..4).any(|i| segments_intersect(a, b, corners[i], corners[(i + 1) % 4]))
+        })
+    }
+
+    fn contains_point(&self, point: DVec3) -> bool {
+        (self.min_z..=self.max_z).contains(&point.z) && self.polygon_contains(point.truncate())
+    }
+}
+
+impl Region for Frustum {
+    fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        Frustum::intersects_aabb(self, aabb)
+    }
+
+    fn contains_point(&self, point: DVec3) -> bool {
+        Frustum::contains_point(self, point)
+    }
+}
+
+pub(crate) fn segments_intersect(a: DVec2, b: DVec2, c: DVec2, d: DVec2) -> bool {
+    let orientation = |p: DVec2, q: DVec2, r: DVec2| (q - p).perp_dot(r - p);
+
+    let (d1, d2) = (orientation(c, d, a), orientation(c, d, b));
+    let (d3, d4) = (orientation(a, b, c), orientation(a, b, d));
+
+    (d1 * d2 <= 0.0) && (d3 * d4 <= 0.0)
+}
@@ -1,8 +1,11 @@
 use crate::octree::aabb::Aabb;
 use crate::octree::node::OctreeNode;
-use crate::octree::NodeId;
+use crate::octree::{FlatOctree, NodeId};
+use crate::point_cloud::ReadHierarchyError;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct OctreeNodeSnapshot {
     pub id: Option<NodeId>,
     pub index: usize,
@@ -40,4 +43,134 @@ impl From<&OctreeNode> for OctreeNodeSnapshot {
             children: [0; 8],
         }
     }
-}
\ No newline at end of file
+}
+
+impl From<&OctreeNodeSnapshot> for OctreeNode {
+    fn from(snapshot: &OctreeNodeSnapshot) -> Self {
+        Self {
+            name: snapshot.name.clone(),
+            bounding_box: snapshot.bounding_box.clone(),
+            spacing: snapshot.spacing,
+            level: snapshot.level,
+            node_type: snapshot.node_type,
+            num_points: snapshot.num_points,
+            byte_offset: snapshot.byte_offset,
+            byte_size: snapshot.byte_size,
+            hierarchy_byte_offset: snapshot.hierarchy_byte_offset,
+            hierarchy_byte_size: snapshot.hierarchy_byte_size,
+            // ids are not valid anymore in the rebuilt octree
+            id: None,
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Nodes of the loaded hierarchy which changed since a version,
+/// see `PotreePointCloud::hierarchy_delta`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HierarchyDelta {
+    /// Version of the hierarchy the delta leads to.
+    pub version: u64,
+    /// Snapshots of the changed subtrees, each one replacing the subtree of the node
+    /// named as its first node.
+    pub subtrees: Vec<Vec<OctreeNodeSnapshot>>,
+}
+
+impl FlatOctree<OctreeNode> {
+    /// Rebuilds an octree from a snapshot taken with `PotreePointCloud::hierarchy_snapshot`.
+    ///
+    /// The first node of the snapshot is the root. Node ids are not preserved,
+    /// the `id` field of the rebuilt nodes refers to the new octree.
+    pub fn from_snapshot(nodes: &[OctreeNodeSnapshot]) -> Result<Self, ReadHierarchyError> {
+        let mut octree = Self::new();
+        let root_id = octree.root_id();
+        octree.replace_with_snapshot(root_id, nodes)?;
+
+        Ok(octree)
+    }
+
+    /// Replaces `node_id` and its descendants with a snapshot whose first node is `node_id`.
+    ///
+    /// The node keeps its id and its parent. Returns the removed descendants.
+    pub fn replace_with_snapshot(
+        &mut self,
+        node_id: NodeId,
+        nodes: &[OctreeNodeSnapshot],
+    ) -> Result<Vec<OctreeNode>, ReadHierarchyError> {
+        if !self.contains(node_id) {
+            return Err(ReadHierarchyError::NodeNotFound);
+        }
+        let children = snapshot_children(nodes)?;
+
+        let removed = self.remove_descendants(node_id);
+        self.reserve(nodes.len());
+
+        let node = self.node_mut(node_id).unwrap();
+        let parent = node.parent;
+        *node = (&nodes[0]).into();
+        node.id = Some(node_id);
+        node.parent = parent;
+
+        let mut queue = VecDeque::from([(0, node_id)]);
+        while let Some((index, node_id)) = queue.pop_front() {
+            let mut child_ids = Vec::with_capacity(children[index].len());
+
+            for child_index in &children[index] {
+                let mut child: OctreeNode = (&nodes[*child_index]).into();
+                child.parent = Some(node_id);
+                let child_id = self.insert(child);
+                self.node_mut(child_id).unwrap().id = Some(child_id);
+
+                child_ids.push(child_id);
+                queue.push_back((*child_index, child_id));
+            }
+
+            self.node_mut(node_id).unwrap().children = child_ids;
+        }
+
+        Ok(removed)
+    }
+}
+
+// Checks that the snapshot is a tree whose root is its first node,
+// and returns the children of each node, sorted as in the hierarchy
+fn snapshot_children(nodes: &[OctreeNodeSnapshot]) -> Result<Vec<Vec<usize>>, ReadHierarchyError> {
+    if nodes.is_empty() {
+        return Err(ReadHierarchyError::InvalidSnapshot(
+            "empty snapshot".to_string(),
+        ));
+    }
+
+    // snapshot indices already visited, to reject cycles and shared children
+    let mut visited = vec![false; nodes.len()];
+    visited[0] = true;
+
+    let mut children = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let mut child_indices: Vec<usize> = node
+            .children
+            .iter()
+            .copied()
+            .filter(|child_index| *child_index != 0)
+            .collect();
+
+        for child_index in &child_indices {
+            match visited.get_mut(*child_index) {
+                Some(visited @ false) => *visited = true,
+                _ => {
+                    return Err(ReadHierarchyError::InvalidSnapshot(format!(
+                        "invalid child {} of node {}",
+                        child_index, node.name
+                    )));
+                }
+            }
+        }
+
+        // children are stored in any order, sort them as in the hierarchy
+        child_indices.sort_by(|a, b| nodes[*a].name.cmp(&nodes[*b].name));
+        children.push(child_indices);
+    }
+
+    Ok(children)
+}
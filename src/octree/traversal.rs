@@ -0,0 +1,233 @@
+use super::node::OctreeNode;
+use super::{FlatOctree, NodeId};
+use std::collections::VecDeque;
+
+/// A node linked to its parent and children, which can be traversed in a [`FlatOctree`].
+pub trait TreeNode {
+    fn parent(&self) -> Option<NodeId>;
+
+    fn children(&self) -> &[NodeId];
+
+    fn children_mut(&mut self) -> &mut Vec<NodeId>;
+}
+
+impl TreeNode for OctreeNode {
+    fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    fn children_mut(&mut self) -> &mut Vec<NodeId> {
+        &mut self.children
+    }
+}
+
+/// Default pruning callback, never prunes.
+pub type NoPruning<T> = fn(NodeId, &T) -> bool;
+
+/// Depth first (pre-order) iterator, see [`FlatOctree::depth_first`].
+pub struct DepthFirst<'a, T, P = NoPruning<T>> {
+    octree: &'a FlatOctree<T>,
+    stack: Vec<NodeId>,
+    prune: P,
+}
+
+impl<'a, T, P> Iterator for DepthFirst<'a, T, P>
+where
+    T: TreeNode,
+    P: FnMut(NodeId, &T) -> bool,
+{
+    type Item = (NodeId, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a stale id, e.g. of an unloaded node, is skipped
+        let (node_id, node) = std::iter::from_fn(|| self.stack.pop())
+            .find_map(|node_id| Some((node_id, self.octree.node(node_id)?)))?;
+
+        if !(self.prune)(node_id, node) {
+            // push in reverse order to visit children in order
+            self.stack.extend(node.children().iter().rev());
+        }
+
+        Some((node_id, node))
+    }
+}
+
+/// Breadth first iterator, see [`FlatOctree::breadth_first`].
+pub struct BreadthFirst<'a, T, P = NoPruning<T>> {
+    octree: &'a FlatOctree<T>,
+    queue: VecDeque<NodeId>,
+    prune: P,
+}
+
+impl<'a, T, P> Iterator for BreadthFirst<'a, T, P>
+where
+    T: TreeNode,
+    P: FnMut(NodeId, &T) -> bool,
+{
+    type Item = (NodeId, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a stale id, e.g. of an unloaded node, is skipped
+        let (node_id, node) = std::iter::from_fn(|| self.queue.pop_front())
+            .find_map(|node_id| Some((node_id, self.octree.node(node_id)?)))?;
+
+        if !(self.prune)(node_id, node) {
+            self.queue.extend(node.children());
+        }
+
+        Some((node_id, node))
+    }
+}
+
+/// Iterator over the ancestors of a node, see [`FlatOctree::ancestors`].
+pub struct Ancestors<'a, T> {
+    octree: &'a FlatOctree<T>,
+    next: Option<NodeId>,
+}
+
+impl<'a, T> Iterator for Ancestors<'a, T>
+where
+    T: TreeNode,
+{
+    type Item = (NodeId, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_id = self.next.take()?;
+        let node = self.octree.node(node_id)?;
+        self.next = node.parent();
+
+        Some((node_id, node))
+    }
+}
+
+impl<T> FlatOctree<T>
+where
+    T: TreeNode,
+{
+    /// Visit the subtree of `node_id` depth first, parents before their children.
+    ///
+    /// The iterator is empty if `node_id` is not in the octree, e.g. an unloaded node.
+    pub fn depth_first(&self, node_id: NodeId) -> DepthFirst<'_, T> {
+        self.depth_first_pruned(node_id, |_, _| false)
+    }
+
+    /// Visit the subtree of `node_id` depth first, skipping the children of the nodes
+    /// for which `prune` returns true. Pruned nodes are still returned.
+    pub fn depth_first_pruned<P>(&self, node_id: NodeId, prune: P) -> DepthFirst<'_, T, P>
+    where
+        P: FnMut(NodeId, &T) -> bool,
+    {
+        DepthFirst {
+            octree: self,
+            stack: vec![node_id],
+            prune,
+        }
+    }
+
+    /// Visit the subtree of `node_id` level by level.
+    ///
+    /// The iterator is empty if `node_id` is not in the octree, e.g. an unloaded node.
+    pub fn breadth_first(&self, node_id: NodeId) -> BreadthFirst<'_, T> {
+        self.breadth_first_pruned(node_id, |_, _| false)
+    }
+
+    /// Visit the subtree of `node_id` level by level, skipping the children of the nodes
+    /// for which `prune` returns true. Pruned nodes are still returned.
+    pub fn breadth_first_pruned<P>(&self, node_id: NodeId, prune: P) -> BreadthFirst<'_, T, P>
+    where
+        P: FnMut(NodeId, &T) -> bool,
+    {
+        BreadthFirst {
+            octree: self,
+            queue: VecDeque::from([node_id]),
+            prune,
+        }
+    }
+
+    /// The loaded nodes `depth` levels below the root.
+    pub fn nodes_at_depth(&self, depth: u32) -> impl Iterator<Item = (NodeId, &T)> {
+        // nodes to visit, with their depth
+        let mut queue = VecDeque::from([(self.root_id, 0)]);
+
+        std::iter::from_fn(move || {
+            while let Some((node_id, node_depth)) = queue.pop_front() {
+                let Some(node) = self.node(node_id) else {
+                    continue;
+                };
+
+                if node_depth == depth {
+                    return Some((node_id, node));
+                }
+                queue.extend(node.children().iter().map(|child| (*child, node_depth + 1)));
+            }
+
+            None
+        })
+    }
+
+    /// The nodes of the subtree of `node_id` without loaded children.
+    pub fn leaves(&self, node_id: NodeId) -> impl Iterator<Item = (NodeId, &T)> {
+        self.depth_first(node_id)
+            .filter(|(_, node)| node.children().is_empty())
+    }
+
+    /// The ancestors of `node_id`, from its parent up to the root.
+    pub fn ancestors(&self, node_id: NodeId) -> Ancestors<'_, T> {
+        Ancestors {
+            octree: self,
+            next: self.node(node_id).and_then(|node| node.parent()),
+        }
+    }
+
+    /// The nodes of the subtree of `node_id`, depth first, without `node_id` itself.
+    pub fn descendants(&self, node_id: NodeId) -> impl Iterator<Item = (NodeId, &T)> {
+        self.depth_first(node_id).skip(1)
+    }
+
+    /// Removes the descendants of `node_id` and returns them, depth first.
+    ///
+    /// `node_id` is kept, without children. The ids of the removed nodes become invalid.
+    pub fn remove_descendants(&mut self, node_id: NodeId) -> Vec<T> {
+        let descendants: Vec<NodeId> = self
+            .descendants(node_id)
+            .map(|(descendant_id, _)| descendant_id)
+            .collect();
+
+        if let Some(node) = self.node_mut(node_id) {
+            node.children_mut().clear();
+        }
+
+        descendants
+            .into_iter()
+            .filter_map(|descendant_id| self.remove(descendant_id))
+            .collect()
+    }
+
+    /// Removes `node_id` and its descendants, and detaches it from its parent.
+    ///
+    /// Returns the removed nodes, depth first. The root node can't be removed.
+    pub fn remove_subtree(&mut self, node_id: NodeId) -> Vec<T> {
+        if node_id == self.root_id() {
+            return Vec::new();
+        }
+        let Some(parent_id) = self.node(node_id).map(|node| node.parent()) else {
+            return Vec::new();
+        };
+
+        if let Some(parent) = parent_id.and_then(|parent_id| self.node_mut(parent_id)) {
+            parent
+                .children_mut()
+                .retain(|child_id| *child_id != node_id);
+        }
+
+        let descendants = self.remove_descendants(node_id);
+        self.remove(node_id)
+            .into_iter()
+            .chain(descendants)
+            .collect()
+    }
+}
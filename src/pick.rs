@@ -0,0 +1,143 @@
+//! Picking the point of a point cloud hit by a ray, e.g. under the mouse cursor.
+
+use crate::octree::NodeId;
+use crate::point::buffer::{AttributeSelection, PointBuffer};
+use crate::point_cloud::{DEFAULT_MAX_CONCURRENT_REQUESTS, PotreePointCloud};
+use crate::query::QueryError;
+use futures::StreamExt;
+use futures::stream;
+use glam::DVec3;
+
+/// The point nearest to the origin of a ray, among the points close enough to the ray.
+#[derive(Clone, Debug)]
+pub struct PickHit {
+    pub node_id: NodeId,
+    /// Index of the point in its node
+    pub index: usize,
+    pub position: DVec3,
+    /// Distance from the ray origin to the projection of the point on the ray
+    pub distance: f64,
+    /// The picked point, with all its attributes
+    pub point: PointBuffer,
+}
+
+impl PotreePointCloud {
+    /// Returns the nearest point along the ray which is at most `pick_radius` away from it,
+    /// ignoring the points further than `max_distance` from `ray_origin`.
+    ///
+    /// The octree is walked level by level. The nodes of a level whose bounding box is
+    /// crossed by the ray are loaded front to back, and a node is skipped along with its
+    /// children once a nearer hit has been found. The hierarchy chunks of the crossed
+    /// proxies are loaded on the way.
+    pub async fn pick(
+        &mut self,
+        ray_origin: DVec3,
+        ray_dir: DVec3,
+        max_distance: f64,
+        pick_radius: f64,
+    ) -> Result<Option<PickHit>, QueryError> {
+        let ray_dir = ray_dir.normalize_or_zero();
+        if ray_dir == DVec3::ZERO {
+            return Ok(None);
+        }
+
+        let mut best: Option<PickHit> = None;
+        let mut level = vec![self.octree().root_id()];
+
+        while !level.is_empty() {
+            // the nodes crossed by the ray, front to back
+            let mut candidates: Vec<(f64, NodeId)> = level
+                .into_iter()
+                .filter_map(|node_id| {
+                    let node = self.octree().node(node_id)?;
+                    // a point close enough to the ray is inside its node's box grown by
+                    // the radius, so its projection on the ray is never before the entry
+                    let (entry, _) = node
+                        .bounding_box
+                        .expand(pick_radius)
+                        .intersect_ray(ray_origin, ray_dir)?;
+                    (entry <= max_distance).then_some((entry, node_id))
+                })
+                .collect();
+            candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            {
+                let mut buffers = stream::iter(candidates.iter())
+                    .map(|(_, node_id)| {
+                        self.load_point_buffer_with(*node_id, &AttributeSelection::All)
+                    })
+                    .buffered(DEFAULT_MAX_CONCURRENT_REQUESTS);
+
+                for (entry, node_id) in &candidates {
+                    if best.as_ref().is_some_and(|hit| hit.distance < *entry) {
+                        break;
+                    }
+
+                    let buffer = buffers
+                        .next()
+                        .await
+                        .expect("one buffer per candidate, shouldn't happen")?;
+
+                    if let Some((index, distance)) =
+                        nearest_hit(&buffer, ray_origin, ray_dir, max_distance, pick_radius)
+                        && best.as_ref().is_none_or(|hit| distance < hit.distance)
+                    {
+                        best = Some(PickHit {
+                            node_id: *node_id,
+                            index,
+                            position: buffer.positions[index],
+                            distance,
+                            point: buffer.filter(|i| i == index),
+                        });
+                    }
+                }
+            }
+
+            // descend into the nodes which may still hold a nearer hit
+            level = Vec::new();
+            for (entry, node_id) in candidates {
+                if best.as_ref().is_some_and(|hit| hit.distance < entry) {
+                    break;
+                }
+
+                let node = self
+                    .octree()
+                    .node(node_id)
+                    .expect("missing node in hierarchy, shouldn't happen");
+                if node.node_type == 2 {
+                    self.load_hierarchy(node_id).await?;
+                }
+
+                let node = self
+                    .octree()
+                    .node(node_id)
+                    .expect("missing node in hierarchy, shouldn't happen");
+                level.extend(node.children.iter().copied());
+            }
+        }
+
+        Ok(best)
+    }
+}
+
+// Index and distance along the ray of the nearest point close enough to the ray.
+fn nearest_hit(
+    buffer: &PointBuffer,
+    origin: DVec3,
+    dir: DVec3,
+    max_distance: f64,
+    radius: f64,
+) -> Option<(usize, f64)> {
+    buffer
+        .positions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, position)| {
+            let distance = (*position - origin).dot(dir);
+            let offset = *position - (origin + dir * distance);
+
+            ((0.0..=max_distance).contains(&distance) && offset.length_squared() <= radius * radius)
+                .then_some((index, distance))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
@@ -0,0 +1,512 @@
+use crate::metadata::{AttributeMetadata, AttributeType};
+use crate::point::PointData;
+use glam::{DVec3, U8Vec3};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AppendError {
+    #[error("Attribute {0} is not in both buffers")]
+    AttributeMismatch(String),
+
+    #[error("Attribute {name} has a different type or number of elements in both buffers")]
+    AttributeTypeMismatch { name: String },
+
+    #[error("Unable to append {actual:?} values to {expected:?} values")]
+    TypeMismatch {
+        expected: AttributeType,
+        actual: AttributeType,
+    },
+}
+
+/// Decoded points of a node, stored as columns.
+///
+/// Positions are always decoded to world coordinates. Every other attribute
+/// declared in the metadata is kept as a typed column, in the metadata order.
+#[derive(Clone, Debug, Default)]
+pub struct PointBuffer {
+    pub num_points: usize,
+    pub positions: Vec<DVec3>,
+    pub attributes: Vec<AttributeBuffer>,
+}
+
+/// A typed column holding `num_elements` values per point.
+#[derive(Clone, Debug)]
+pub struct AttributeBuffer {
+    pub name: String,
+    pub num_elements: u16,
+    pub data: AttributeData,
+}
+
+#[derive(Clone, Debug)]
+pub enum AttributeData {
+    Int8(Vec<i8>),
+    Int16(Vec<i16>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    UInt8(Vec<u8>),
+    UInt16(Vec<u16>),
+    UInt32(Vec<u32>),
+    UInt64(Vec<u64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    // Raw bytes of attributes whose type is unknown, `size` bytes per point
+    Undefined(Vec<u8>),
+}
+
+/// Attributes to decode when loading points.
+///
+/// Positions are selected with either `position` or `POSITION_CARTESIAN`.
+#[derive(Clone, Debug, Default)]
+pub enum AttributeSelection {
+    #[default]
+    All,
+    Only(Vec<String>),
+}
+
+impl AttributeSelection {
+    pub fn only<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self::Only(names.into_iter().map(Into::into).collect())
+    }
+
+    /// Returns true if the attribute named `name` must be decoded.
+    pub fn contains(&self, name: &str) -> bool {
+        match self {
+            AttributeSelection::All => true,
+            AttributeSelection::Only(names) => names.iter().any(|selected| {
+                selected == name || (is_position_attribute(selected) && is_position_attribute(name))
+            }),
+        }
+    }
+}
+
+impl PointBuffer {
+    pub fn new(num_points: usize) -> Self {
+        Self {
+            num_points,
+            positions: Vec::new(),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Returns the attribute named `name`, if it was decoded.
+    pub fn attribute(&self, name: &str) -> Option<&AttributeBuffer> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+    }
+
+    pub fn attribute_mut(&mut self, name: &str) -> Option<&mut AttributeBuffer> {
+        self.attributes
+            .iter_mut()
+            .find(|attribute| attribute.name == name)
+    }
+
+    /// Returns the color column, if any.
+    pub fn colors(&self) -> Option<&AttributeBuffer> {
+        self.attributes
+            .iter()
+            .find(|attribute| is_color_attribute(&attribute.name))
+    }
+
+    /// Converts the buffer to the row-oriented `PointData` representation.
+    pub fn to_point_data(&self) -> Vec<PointData> {
+        let mut points = vec![PointData::default(); self.num_points];
+
+        for (point, position) in points.iter_mut().zip(&self.positions) {
+            point.position = *position;
+        }
+
+        if let Some(colors) = self.colors() {
+            let num_elements = colors.num_elements as usize;
+            for (i, point) in points.iter_mut().enumerate() {
+                let r = colors.data.get_f64(i * num_elements).unwrap_or_default();
+                let g = colors
+                    .data
+                    .get_f64(i * num_elements + 1)
+                    .unwrap_or_default();
+                let b = colors
+                    .data
+                    .get_f64(i * num_elements + 2)
+                    .unwrap_or_default();
+
+                point.color = U8Vec3::new(
+                    to_u8_color(&colors.data, r),
+                    to_u8_color(&colors.data, g),
+                    to_u8_color(&colors.data, b),
+                );
+            }
+        }
+
+        points
+    }
+
+    /// Returns a buffer holding only the points for which `keep` returns true.
+    pub fn filter<F>(&self, mut keep: F) -> PointBuffer
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let indices: Vec<usize> = (0..self.num_points).filter(|i| keep(*i)).collect();
+
+        self.select(&indices)
+    }
+
+    /// Returns a buffer holding the points at `indices`, in this order.
+    pub fn select(&self, indices: &[usize]) -> PointBuffer {
+        PointBuffer {
+            num_points: indices.len(),
+            positions: indices
+                .iter()
+                .filter_map(|i| self.positions.get(*i).copied())
+                .collect(),
+            attributes: self
+                .attributes
+                .iter()
+                .map(|attribute| AttributeBuffer {
+                    name: attribute.name.clone(),
+                    num_elements: attribute.num_elements,
+                    data: attribute.data.select(indices, self.num_points),
+                })
+                .collect(),
+        }
+    }
+
+    /// Appends the points of `other`, which must hold the same attributes, in any order.
+    ///
+    /// The columns are matched by name. Nothing is appended if an attribute is missing
+    /// from one of the buffers, or has another type or number of elements.
+    pub fn append(&mut self, other: PointBuffer) -> Result<(), AppendError> {
+        if self.num_points == 0 && self.attributes.is_empty() {
+            *self = other;
+            return Ok(());
+        }
+
+        if let Some(attribute) = other
+            .attributes
+            .iter()
+            .find(|attribute| self.attribute(&attribute.name).is_none())
+        {
+            return Err(AppendError::AttributeMismatch(attribute.name.clone()));
+        }
+        for attribute in &self.attributes {
+            let other = other
+                .attribute(&attribute.name)
+                .ok_or_else(|| AppendError::AttributeMismatch(attribute.name.clone()))?;
+            if other.num_elements != attribute.num_elements
+                || other.data.r#type() != attribute.data.r#type()
+            {
+                return Err(AppendError::AttributeTypeMismatch {
+                    name: attribute.name.clone(),
+                });
+            }
+        }
+
+        self.num_points += other.num_points;
+        self.positions.extend(other.positions);
+        for other in other.attributes {
+            self.attribute_mut(&other.name)
+                .expect("attributes checked above")
+                .data
+                .append(other.data)
+                .expect("types checked above");
+        }
+
+        Ok(())
+    }
+}
+
+impl AttributeBuffer {
+    /// Creates an empty column able to hold `num_points` values of `attribute`.
+    pub fn with_capacity(attribute: &AttributeMetadata, num_points: usize) -> Self {
+        let capacity = num_points * attribute.num_elements as usize;
+        let data = match attribute.r#type {
+            AttributeType::Int8 => AttributeData::Int8(Vec::with_capacity(capacity)),
+            AttributeType::Int16 => AttributeData::Int16(Vec::with_capacity(capacity)),
+            AttributeType::Int32 => AttributeData::Int32(Vec::with_capacity(capacity)),
+            AttributeType::Int64 => AttributeData::Int64(Vec::with_capacity(capacity)),
+            AttributeType::UInt8 => AttributeData::UInt8(Vec::with_capacity(capacity)),
+            AttributeType::UInt16 => AttributeData::UInt16(Vec::with_capacity(capacity)),
+            AttributeType::UInt32 => AttributeData::UInt32(Vec::with_capacity(capacity)),
+            AttributeType::UInt64 => AttributeData::UInt64(Vec::with_capacity(capacity)),
+            AttributeType::Float => AttributeData::Float(Vec::with_capacity(capacity)),
+            AttributeType::Double => AttributeData::Double(Vec::with_capacity(capacity)),
+            AttributeType::Undefined => {
+                AttributeData::Undefined(Vec::with_capacity(num_points * attribute.size as usize))
+            }
+        };
+
+        Self {
+            name: attribute.name.clone(),
+            num_elements: attribute.num_elements,
+            data,
+        }
+    }
+
+    /// Returns the value of element `element` of point `index`, converted to `f64`.
+    ///
+    /// Undefined attributes hold raw bytes, not values, and return `None`.
+    pub fn get_f64(&self, index: usize, element: usize) -> Option<f64> {
+        if matches!(self.data, AttributeData::Undefined(_)) {
+            return None;
+        }
+
+        self.data
+            .get_f64(index * self.num_elements as usize + element)
+    }
+}
+
+impl AttributeData {
+    /// Number of values (not points) stored in the column.
+    pub fn len(&self) -> usize {
+        match self {
+            AttributeData::Int8(values) => values.len(),
+            AttributeData::Int16(values) => values.len(),
+            AttributeData::Int32(values) => values.len(),
+            AttributeData::Int64(values) => values.len(),
+            AttributeData::UInt8(values) => values.len(),
+            AttributeData::UInt16(values) => values.len(),
+            AttributeData::UInt32(values) => values.len(),
+            AttributeData::UInt64(values) => values.len(),
+            AttributeData::Float(values) => values.len(),
+            AttributeData::Double(values) => values.len(),
+            AttributeData::Undefined(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Type of the values, `Undefined` for raw bytes.
+    pub fn r#type(&self) -> AttributeType {
+        match self {
+            AttributeData::Int8(_) => AttributeType::Int8,
+            AttributeData::Int16(_) => AttributeType::Int16,
+            AttributeData::Int32(_) => AttributeType::Int32,
+            AttributeData::Int64(_) => AttributeType::Int64,
+            AttributeData::UInt8(_) => AttributeType::UInt8,
+            AttributeData::UInt16(_) => AttributeType::UInt16,
+            AttributeData::UInt32(_) => AttributeType::UInt32,
+            AttributeData::UInt64(_) => AttributeType::UInt64,
+            AttributeData::Float(_) => AttributeType::Float,
+            AttributeData::Double(_) => AttributeType::Double,
+            AttributeData::Undefined(_) => AttributeType::Undefined,
+        }
+    }
+
+    /// Returns the value at `index`, converted to `f64`.
+    pub fn get_f64(&self, index: usize) -> Option<f64> {
+        match self {
+            AttributeData::Int8(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::Int16(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::Int32(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::Int64(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::UInt8(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::UInt16(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::UInt32(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::UInt64(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::Float(values) => values.get(index).map(|v| *v as f64),
+            AttributeData::Double(values) => values.get(index).copied(),
+            AttributeData::Undefined(values) => values.get(index).map(|v| *v as f64),
+        }
+    }
+
+    /// Returns the values of the points at `indices`, the column holding `num_points` points.
+    pub fn select(&self, indices: &[usize], num_points: usize) -> AttributeData {
+        // values (or bytes for undefined attributes) per point
+        let stride = self.len().checked_div(num_points).unwrap_or_default();
+
+        match self {
+            AttributeData::Int8(values) => AttributeData::Int8(select(values, indices, stride)),
+            AttributeData::Int16(values) => AttributeData::Int16(select(values, indices, stride)),
+            AttributeData::Int32(values) => AttributeData::Int32(select(values, indices, stride)),
+            AttributeData::Int64(values) => AttributeData::Int64(select(values, indices, stride)),
+            AttributeData::UInt8(values) => AttributeData::UInt8(select(values, indices, stride)),
+            AttributeData::UInt16(values) => AttributeData::UInt16(select(values, indices, stride)),
+            AttributeData::UInt32(values) => AttributeData::UInt32(select(values, indices, stride)),
+            AttributeData::UInt64(values) => AttributeData::UInt64(select(values, indices, stride)),
+            AttributeData::Float(values) => AttributeData::Float(select(values, indices, stride)),
+            AttributeData::Double(values) => AttributeData::Double(select(values, indices, stride)),
+            AttributeData::Undefined(values) => {
+                AttributeData::Undefined(select(values, indices, stride))
+            }
+        }
+    }
+
+    /// Appends the values of `other`, which must have the same type.
+    pub fn append(&mut self, other: AttributeData) -> Result<(), AppendError> {
+        match (self, other) {
+            (AttributeData::Int8(values), AttributeData::Int8(other)) => values.extend(other),
+            (AttributeData::Int16(values), AttributeData::Int16(other)) => values.extend(other),
+            (AttributeData::Int32(values), AttributeData::Int32(other)) => values.extend(other),
+            (AttributeData::Int64(values), AttributeData::Int64(other)) => values.extend(other),
+            (AttributeData::UInt8(values), AttributeData::UInt8(other)) => values.extend(other),
+            (AttributeData::UInt16(values), AttributeData::UInt16(other)) => values.extend(other),
+            (AttributeData::UInt32(values), AttributeData::UInt32(other)) => values.extend(other),
+            (AttributeData::UInt64(values), AttributeData::UInt64(other)) => values.extend(other),
+            (AttributeData::Float(values), AttributeData::Float(other)) => values.extend(other),
+            (AttributeData::Double(values), AttributeData::Double(other)) => values.extend(other),
+            (AttributeData::Undefined(values), AttributeData::Undefined(other)) => {
+                values.extend(other)
+            }
+            (values, other) => {
+                return Err(AppendError::TypeMismatch {
+                    expected: values.r#type(),
+                    actual: other.r#type(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes one little-endian value of the column type and appends it.
+    pub(crate) fn push_le_bytes(&mut self, bytes: &[u8]) {
+        match self {
+            AttributeData::Int8(values) => values.push(bytes[0] as i8),
+            AttributeData::Int16(values) => values.push(i16::from_le_bytes([bytes[0], bytes[1]])),
+            AttributeData::Int32(values) => {
+                values.push(i32::from_le_bytes(bytes[..4].try_into().unwrap()))
+            }
+            AttributeData::Int64(values) => {
+                values.push(i64::from_le_bytes(bytes[..8].try_into().unwrap()))
+            }
+            AttributeData::UInt8(values) => values.push(bytes[0]),
+            AttributeData::UInt16(values) => values.push(u16::from_le_bytes([bytes[0], bytes[1]])),
+            AttributeData::UInt32(values) => {
+                values.push(u32::from_le_bytes(bytes[..4].try_into().unwrap()))
+            }
+            AttributeData::UInt64(values) => {
+                values.push(u64::from_le_bytes(bytes[..8].try_into().unwrap()))
+            }
+            AttributeData::Float(values) => {
+                values.push(f32::from_le_bytes(bytes[..4].try_into().unwrap()))
+            }
+            AttributeData::Double(values) => {
+                values.push(f64::from_le_bytes(bytes[..8].try_into().unwrap()))
+            }
+            AttributeData::Undefined(values) => values.extend_from_slice(bytes),
+        }
+    }
+
+    /// Appends the value at `index` as little-endian bytes of type `r#type`,
+    /// converting it if the column has another type.
+    pub(crate) fn extend_le_bytes(&self, index: usize, r#type: AttributeType, bytes: &mut Vec<u8>) {
+        match (self, r#type) {
+            (AttributeData::Int8(values), AttributeType::Int8) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (AttributeData::Int16(values), AttributeType::Int16) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (AttributeData::Int32(values), AttributeType::Int32) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (AttributeData::Int64(values), AttributeType::Int64) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (AttributeData::UInt8(values), AttributeType::UInt8 | AttributeType::Undefined)
+            | (AttributeData::Undefined(values), AttributeType::UInt8 | AttributeType::Undefined) => {
+                bytes.push(values[index])
+            }
+            (AttributeData::UInt16(values), AttributeType::UInt16) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (AttributeData::UInt32(values), AttributeType::UInt32) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (AttributeData::UInt64(values), AttributeType::UInt64) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (AttributeData::Float(values), AttributeType::Float) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (AttributeData::Double(values), AttributeType::Double) => {
+                bytes.extend(values[index].to_le_bytes())
+            }
+            (data, r#type) => {
+                let value = data.get_f64(index).unwrap_or_default();
+                match r#type {
+                    AttributeType::Int8 => bytes.extend((value as i8).to_le_bytes()),
+                    AttributeType::Int16 => bytes.extend((value as i16).to_le_bytes()),
+                    AttributeType::Int32 => bytes.extend((value as i32).to_le_bytes()),
+                    AttributeType::Int64 => bytes.extend((value as i64).to_le_bytes()),
+                    AttributeType::UInt8 | AttributeType::Undefined => bytes.push(value as u8),
+                    AttributeType::UInt16 => bytes.extend((value as u16).to_le_bytes()),
+                    AttributeType::UInt32 => bytes.extend((value as u32).to_le_bytes()),
+                    AttributeType::UInt64 => bytes.extend((value as u64).to_le_bytes()),
+                    AttributeType::Float => bytes.extend((value as f32).to_le_bytes()),
+                    AttributeType::Double => bytes.extend(value.to_le_bytes()),
+                }
+            }
+        }
+    }
+}
+
+// Copies the `stride` values of each point at `indices`
+fn select<T: Copy>(values: &[T], indices: &[usize], stride: usize) -> Vec<T> {
+    let mut selected = Vec::with_capacity(indices.len() * stride);
+    for i in indices {
+        selected.extend_from_slice(&values[i * stride..(i + 1) * stride]);
+    }
+
+    selected
+}
+
+pub(crate) fn is_position_attribute(name: &str) -> bool {
+    matches!(name, "POSITION_CARTESIAN" | "position")
+}
+
+pub(crate) fn is_color_attribute(name: &str) -> bool {
+    matches!(name, "RGBA" | "rgba" | "RGB" | "rgb")
+}
+
+/// Converts a color value of the column to 8 bits.
+///
+/// The scale depends on the type of the column, not on the value, so dark 16 bits colors
+/// are not taken for 8 bits ones: 8 bits colors are kept, the other ones are scaled down.
+pub(crate) fn to_u8_color(data: &AttributeData, value: f64) -> u8 {
+    match data {
+        AttributeData::UInt8(_) | AttributeData::Int8(_) => value as u8,
+        _ => (value / 257.0).round() as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_f64_of_undefined_attribute() {
+        let column = AttributeBuffer {
+            name: "raw".to_string(),
+            num_elements: 1,
+            data: AttributeData::Undefined(vec![1, 2, 3, 4, 5, 6]),
+        };
+
+        assert_eq!(column.get_f64(0, 0), None);
+        assert_eq!(column.get_f64(1, 0), None);
+    }
+
+    #[test]
+    fn dark_16_bits_colors_are_scaled() {
+        let points = PointBuffer {
+            num_points: 1,
+            positions: vec![DVec3::ZERO],
+            attributes: vec![AttributeBuffer {
+                name: "rgb".to_string(),
+                num_elements: 3,
+                data: AttributeData::UInt16(vec![0, 200, 65535]),
+            }],
+        };
+
+        assert_eq!(points.to_point_data()[0].color, U8Vec3::new(0, 1, 255));
+        assert_eq!(
+            points.into_gpu_buffer(DVec3::ZERO).colors,
+            vec![u32::from_le_bytes([0, 1, 255, 255])]
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use crate::point::buffer::{AttributeBuffer, PointBuffer, is_color_attribute, to_u8_color};
+use glam::DVec3;
+
+/// Points of a node laid out as vertex buffers.
+///
+/// Positions are stored as `f32`, relative to `origin`, so they keep their precision
+/// whatever the magnitude of the world coordinates.
+#[derive(Clone, Debug, Default)]
+pub struct GpuPointBuffer {
+    pub num_points: usize,
+    /// World position the positions are relative to.
+    pub origin: DVec3,
+    /// Interleaved `x, y, z` positions, 3 values per point.
+    pub positions: Vec<f32>,
+    /// Colors packed as `RGBA8`, the red component in the lowest byte.
+    /// Empty if the point cloud has no color.
+    pub colors: Vec<u32>,
+    /// Every other attribute, as typed columns.
+    pub attributes: Vec<AttributeBuffer>,
+}
+
+impl PointBuffer {
+    /// Converts the buffer to vertex buffers whose positions are relative to `origin`.
+    ///
+    /// Attribute columns are moved, not copied.
+    pub fn into_gpu_buffer(self, origin: DVec3) -> GpuPointBuffer {
+        let mut positions = Vec::with_capacity(self.positions.len() * 3);
+        for position in &self.positions {
+            let relative = (*position - origin).as_vec3();
+            positions.extend_from_slice(&relative.to_array());
+        }
+
+        let colors = match self.colors() {
+            Some(colors) => {
+                let num_elements = colors.num_elements as usize;
+                (0..self.num_points)
+                    .map(|i| {
+                        let component = |element: usize| {
+                            if element < num_elements {
+                                colors
+                                    .data
+                                    .get_f64(i * num_elements + element)
+                                    .map(|value| to_u8_color(&colors.data, value))
+                            } else {
+                                None
+                            }
+                        };
+
+                        u32::from_le_bytes([
+                            component(0).unwrap_or_default(),
+                            component(1).unwrap_or_default(),
+                            component(2).unwrap_or_default(),
+                            component(3).unwrap_or(u8::MAX),
+                        ])
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        let attributes = self
+            .attributes
+            .into_iter()
+            .filter(|attribute| !is_color_attribute(&attribute.name))
+            .collect();
+
+        GpuPointBuffer {
+            num_points: self.num_points,
+            origin,
+            positions,
+            colors,
+            attributes,
+        }
+    }
+}
@@ -1,3 +1,6 @@
+pub mod buffer;
+pub mod gpu;
+
 use glam::{DVec3, U8Vec3};
 
 #[derive(Clone, Debug, Default)]
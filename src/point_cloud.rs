@@ -1,16 +1,29 @@
+use crate::cache::{self, CACHE_VERSION, CachedLayout, CachedString, HierarchyCache};
+use crate::decoder::{decode_points, empty_points};
 use crate::hierarchy::HierarchyNodeEntry;
+#[cfg(feature = "las")]
+use crate::import;
+use crate::legacy::{self, CloudJs, LegacyLayout};
 use crate::metadata::Metadata;
-use crate::octree::aabb::create_child_aabb;
+use crate::octree::frustum::FrustumCulling;
+use crate::octree::key::OctreeKey;
+use crate::octree::lod::{LodOptions, LodSelection};
 use crate::octree::node::OctreeNode;
-use crate::octree::snapshot::OctreeNodeSnapshot;
+use crate::octree::snapshot::{HierarchyDelta, OctreeNodeSnapshot};
 use crate::octree::{FlatOctree, NodeId};
 use crate::point::PointData;
+use crate::point::buffer::{AttributeSelection, PointBuffer};
+use crate::point::gpu::GpuPointBuffer;
 use crate::resource::{ResourceError, ResourceLoader};
-use binrw::BinReaderExt;
-use byteorder::{ByteOrder, LittleEndian};
-use glam::{DVec3, U8Vec3, U16Vec3, UVec3, Vec3A};
-use std::io::{Cursor, Read};
+use binrw::{BinReaderExt, BinWriterExt};
+use futures::StreamExt;
+use futures::channel::mpsc::{UnboundedReceiver, UnboundedSender, unbounded};
+use futures::stream::FuturesUnordered;
+use glam::{DMat4, DVec3};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::Cursor;
 use thiserror::Error;
+use tracing::debug;
 
 #[derive(Error, Debug)]
 pub enum LoadPotreePointCloudError {
@@ -22,6 +35,21 @@ pub enum LoadPotreePointCloudError {
 
     #[error("Error loading resource: {0}")]
     ResourceError(#[from] ResourceError),
+
+    #[error("Unsupported attribute: {0}")]
+    UnsupportedAttribute(String),
+
+    #[error("Invalid hierarchy step size {0}")]
+    InvalidStepSize(u16),
+
+    #[cfg(feature = "las")]
+    #[error("Error reading the attributes of the root node: {0}")]
+    Las(#[from] las::Error),
+
+    #[error(
+        "Unsupported encoding {0}, only Potree 2.0 point clouds can be rebuilt from a snapshot"
+    )]
+    UnsupportedSnapshotEncoding(String),
 }
 
 #[derive(Error, Debug)]
@@ -40,6 +68,35 @@ pub enum ReadHierarchyError {
 
     #[error("Invalid binary data")]
     InvalidBinaryData(#[from] binrw::error::Error),
+
+    #[error("Node does not exists")]
+    NodeNotFound,
+
+    #[error("Invalid node name: {0}")]
+    InvalidNodeName(String),
+
+    #[error("Node {0} is not the root of a hierarchy chunk")]
+    NotAChunkRoot(String),
+
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+
+    #[error("Invalid hierarchy size for node {node}: {size} bytes at offset {offset}")]
+    InvalidHierarchySize {
+        node: String,
+        offset: u64,
+        size: usize,
+    },
+
+    #[error(
+        "Invalid child mask in hierarchy of node {node}: entry {entry} requires {required} entries, only {available} available"
+    )]
+    InvalidChildMask {
+        node: String,
+        entry: usize,
+        required: usize,
+        available: usize,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -55,17 +112,149 @@ pub enum LoadPointsError {
 
     #[error("IO Error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error(
+        "Invalid buffer size for node {node} at offset {offset}: expected {expected} bytes, got {actual}"
+    )]
+    InvalidBufferSize {
+        node: String,
+        offset: u64,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("Unable to decompress node {node} at offset {offset}: {source}")]
+    Decompression {
+        node: String,
+        offset: u64,
+        source: std::io::Error,
+    },
+
+    #[error(
+        "Invalid attribute {name}: size {size}, {num_elements} elements of {element_size} bytes"
+    )]
+    InvalidAttribute {
+        name: String,
+        size: u16,
+        num_elements: u16,
+        element_size: u16,
+    },
+
+    #[cfg(feature = "las")]
+    #[error("LAS error: {0}")]
+    Las(#[from] las::Error),
 }
 
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("Invalid cache data: {0}")]
+    InvalidBinaryData(#[from] binrw::error::Error),
+
+    #[error("Unsupported cache version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Invalid cached metadata: {0}")]
+    Json(#[from] serde_json::error::Error),
+
+    #[error("Invalid cached layout: {0}")]
+    InvalidLayout(String),
+
+    #[error("Invalid cached hierarchy: {0}")]
+    ReadHierarchyError(#[from] ReadHierarchyError),
+
+    #[error("Resource error: {0}")]
+    Resource(#[from] ResourceError),
+
+    #[error("The hierarchy has changed since the cache was written")]
+    Stale,
+}
+
+/// A change of the loaded hierarchy, see `PotreePointCloud::subscribe`.
+#[derive(Clone, Debug)]
+pub enum HierarchyEvent {
+    /// The hierarchy below the node has been loaded: a hierarchy chunk has been parsed,
+    /// or a delta has been applied.
+    Loaded {
+        node_id: NodeId,
+        name: String,
+        version: u64,
+    },
+    /// The hierarchy below the node has been unloaded, it is a proxy again.
+    Unloaded {
+        node_id: NodeId,
+        name: String,
+        version: u64,
+    },
+}
+
+/// Number of hierarchy chunks fetched at the same time by `PotreePointCloud::load_entire_hierarchy`.
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 16;
+
+/// Where the hierarchy and the points are stored.
+#[derive(Clone, Debug)]
+enum Layout {
+    /// Potree 2: a single `hierarchy.bin` and a single `octree.bin`
+    Potree2 {
+        hierarchy_url: String,
+        octree_url: String,
+    },
+    /// Potree 1.x: one `.hrc` file per hierarchy chunk and one file per node
+    Legacy(LegacyLayout),
+}
+
+/// Location of a hierarchy chunk: a whole file, or a byte range of it.
 #[derive(Clone, Debug)]
+struct HierarchyRequest {
+    url: String,
+    range: Option<(u64, usize)>,
+}
+
+impl HierarchyRequest {
+    async fn fetch(self, resource_loader: &ResourceLoader) -> Result<Vec<u8>, ResourceError> {
+        match self.range {
+            Some((offset, size)) => {
+                resource_loader
+                    .get_range(&self.url, offset, size, None)
+                    .await
+            }
+            None => resource_loader.get(&self.url, None).await,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct PotreePointCloud {
     metadata: Metadata,
-    hierarchy_url: String,
-    octree_url: String,
+    layout: Layout,
     octree: FlatOctree<OctreeNode>,
+    // Loaded nodes by name
+    names: HashMap<String, NodeId>,
+    // Incremented each time a part of the hierarchy is loaded or unloaded
+    version: u64,
+    // Nodes whose subtree changed, and the last version it changed in. A change covers the
+    // older changes of the descendants, which are dropped, so only loaded nodes are listed.
+    changes: HashMap<String, u64>,
+    subscribers: Vec<UnboundedSender<HierarchyEvent>>,
     resource_loader: ResourceLoader,
 }
 
+impl Clone for PotreePointCloud {
+    /// Clones the point cloud, without the subscribers of the original: the events of
+    /// the clone are only sent to the streams returned by its own [`Self::subscribe`].
+    fn clone(&self) -> Self {
+        Self {
+            metadata: self.metadata.clone(),
+            layout: self.layout.clone(),
+            octree: self.octree.clone(),
+            names: self.names.clone(),
+            version: self.version,
+            changes: self.changes.clone(),
+            subscribers: Vec::new(),
+            resource_loader: self.resource_loader.clone(),
+        }
+    }
+}
+
 impl PotreePointCloud {
     /// Load a Potree point cloud from a URL.
     /// Relatives urls works only if the provided client supports it.
@@ -84,21 +273,120 @@ impl PotreePointCloud {
         let metadata = resource_loader
             .get_json(&metadata_url, None)
             .await
-            .map_err(|error| LoadPotreePointCloudError::ResourceError(error))?;
+            .map_err(LoadPotreePointCloudError::ResourceError)?;
+
+        let mut this = Self {
+            metadata,
+            layout: Layout::Potree2 {
+                hierarchy_url,
+                octree_url: format!("{}/octree.bin", url).to_string(),
+            },
+            octree,
+            names: HashMap::new(),
+            version: 0,
+            changes: HashMap::new(),
+            subscribers: Vec::new(),
+            resource_loader,
+        };
+
+        this.load_initial_hierarchy().await?;
+
+        Ok(this)
+    }
+
+    /// Rebuild a Potree point cloud from its metadata and a snapshot of its hierarchy,
+    /// taken with [`Self::hierarchy_snapshot`], without requesting anything.
+    ///
+    /// The hierarchy and the points are supposed to be accessible relatively to the provided url,
+    /// as for [`Self::from_url`], to load the remaining hierarchy chunks and the points.
+    /// Snapshots of legacy point clouds, with a `BINARY`, `LAS` or `LAZ` encoding, are rejected.
+    pub fn from_snapshot(
+        url: &str,
+        metadata: Metadata,
+        snapshot: &[OctreeNodeSnapshot],
+        resource_loader: ResourceLoader,
+    ) -> Result<PotreePointCloud, LoadPotreePointCloudError> {
+        if matches!(metadata.encoding.as_str(), "BINARY" | "LAS" | "LAZ") {
+            return Err(LoadPotreePointCloudError::UnsupportedSnapshotEncoding(
+                metadata.encoding,
+            ));
+        }
 
         let mut this = Self {
             metadata,
-            hierarchy_url,
-            octree_url: format!("{}/octree.bin", url).to_string(),
+            layout: Layout::Potree2 {
+                hierarchy_url: format!("{}/hierarchy.bin", url),
+                octree_url: format!("{}/octree.bin", url),
+            },
+            octree: FlatOctree::from_snapshot(snapshot)?,
+            names: HashMap::new(),
+            version: 0,
+            changes: HashMap::new(),
+            subscribers: Vec::new(),
+            resource_loader,
+        };
+        this.subtree_changed(this.octree.root_id());
+
+        Ok(this)
+    }
+
+    /// Load a Potree 1.x point cloud from a URL.
+    /// The `cloud.js` file is supposed to be accessible at `<url>/cloud.js`, and the nodes
+    /// in the `octreeDir` directory it declares, relatively to the provided url.
+    /// Nodes stored as `.las`/`.laz` files require the `las`/`laz` features, their attributes
+    /// are those of the point format of the root node, whose header is requested to list them.
+    pub async fn from_legacy_url(
+        url: &str,
+        resource_loader: ResourceLoader,
+    ) -> Result<PotreePointCloud, LoadPotreePointCloudError> {
+        let octree = FlatOctree::new();
+
+        let cloud_js_url = format!("{}/cloud.js", url).to_string();
+        let cloud_js: CloudJs = resource_loader
+            .get_json(&cloud_js_url, None)
+            .await
+            .map_err(LoadPotreePointCloudError::LoadMetadataError)?;
+
+        let mut this = Self {
+            metadata: cloud_js.to_metadata()?,
+            layout: Layout::Legacy(cloud_js.layout(url)),
             octree,
+            names: HashMap::new(),
+            version: 0,
+            changes: HashMap::new(),
+            subscribers: Vec::new(),
             resource_loader,
         };
 
         this.load_initial_hierarchy().await?;
+        #[cfg(feature = "las")]
+        this.load_las_attributes().await?;
 
         Ok(this)
     }
 
+    // Lists the attributes decoded from the `.las`/`.laz` nodes, which depend on their point format
+    #[cfg(feature = "las")]
+    async fn load_las_attributes(&mut self) -> Result<(), LoadPotreePointCloudError> {
+        let Layout::Legacy(layout) = &self.layout else {
+            return Ok(());
+        };
+        if !matches!(self.metadata.encoding.as_str(), "LAS" | "LAZ") {
+            return Ok(());
+        }
+
+        // the point format is in the header, the rest of the node is not needed
+        let header = self
+            .resource_loader
+            .get_range(&layout.node_url("r"), 0, import::MIN_HEADER_SIZE, None)
+            .await?;
+        self.metadata
+            .attributes
+            .extend(import::header_attributes(&header)?);
+
+        Ok(())
+    }
+
     async fn load_initial_hierarchy(&mut self) -> Result<(), ReadHierarchyError> {
         let root_id = self.octree.root_id();
         // get the root node
@@ -118,60 +406,545 @@ impl PotreePointCloud {
 
     pub async fn load_hierarchy(&mut self, node_id: NodeId) -> Result<(), ReadHierarchyError> {
         // get the root node
-        let node = self.octree.node(node_id).unwrap();
+        let node = self
+            .octree
+            .node(node_id)
+            .ok_or(ReadHierarchyError::NodeNotFound)?;
 
         if node.node_type == 2 {
             let data = self
-                .resource_loader
-                .get_range(
-                    &self.hierarchy_url,
-                    node.hierarchy_byte_offset,
-                    node.hierarchy_byte_size as usize,
-                    None,
-                )
+                .hierarchy_request(node)
+                .fetch(&self.resource_loader)
                 .await?;
 
-            self.parse_hierarchy(node_id, &data)?;
+            self.parse_hierarchy_chunk(node_id, &data)?;
         }
 
         Ok(())
     }
 
+    /// Unload the hierarchy below `node_id`, turning it back into a proxy node.
+    ///
+    /// The node must be the root of a hierarchy chunk, so its children can be loaded again
+    /// with [`Self::load_hierarchy`]. The ids of the removed nodes become invalid.
+    pub fn unload_hierarchy(&mut self, node_id: NodeId) -> Result<(), ReadHierarchyError> {
+        let node = self
+            .octree
+            .node(node_id)
+            .ok_or(ReadHierarchyError::NodeNotFound)?;
+
+        // nothing to unload
+        if node.node_type == 2 || node.children.is_empty() {
+            return Ok(());
+        }
+
+        let is_chunk_root = match &self.layout {
+            Layout::Potree2 { .. } => node.hierarchy_byte_size > 0,
+            Layout::Legacy(layout) => node.level % layout.hierarchy_step_size as u32 == 0,
+        };
+        if !is_chunk_root {
+            return Err(ReadHierarchyError::NotAChunkRoot(node.name.clone()));
+        }
+
+        for removed in self.octree.remove_descendants(node_id) {
+            self.names.remove(&removed.name);
+        }
+
+        let node = self
+            .octree
+            .node_mut(node_id)
+            .expect("missing node in hierarchy, shouldn't happen");
+        node.node_type = 2;
+        let name = node.name.clone();
+
+        let version = self.subtree_changed(node_id);
+        self.emit(HierarchyEvent::Unloaded {
+            node_id,
+            name,
+            version,
+        });
+
+        Ok(())
+    }
+
+    /// Serializes the metadata and the loaded hierarchy to a compact binary cache,
+    /// which can be restored with [`Self::from_cache`].
+    ///
+    /// The size and version of the hierarchy are requested to detect later changes. For legacy
+    /// point clouds only the root `.hrc` file is checked, changes of the deeper `.hrc` files
+    /// don't invalidate the cache.
+    pub async fn to_cache(&self) -> Result<Vec<u8>, CacheError> {
+        let source = self.resource_loader.head(&self.hierarchy_source()).await?;
+
+        let layout = match &self.layout {
+            Layout::Potree2 {
+                hierarchy_url,
+                octree_url,
+            } => CachedLayout::Potree2 {
+                hierarchy_url: hierarchy_url.as_str().into(),
+                octree_url: octree_url.as_str().into(),
+            },
+            Layout::Legacy(layout) => CachedLayout::Legacy {
+                octree_url: layout.octree_url.as_str().into(),
+                hierarchy_step_size: layout.hierarchy_step_size,
+                extension: layout.extension.into(),
+            },
+        };
+
+        let cache = HierarchyCache {
+            version: CACHE_VERSION,
+            source: (&source).into(),
+            layout,
+            metadata: CachedString {
+                bytes: serde_json::to_vec(&self.metadata)?,
+            },
+            nodes: cache::cache_nodes(&self.octree),
+        };
+
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.write_le(&cache)?;
+
+        Ok(cursor.into_inner())
+    }
+
+    /// Restores a point cloud from a cache written by [`Self::to_cache`].
+    ///
+    /// Returns [`CacheError::Stale`] if the hierarchy has changed since the cache was written,
+    /// in which case the point cloud should be loaded again from its url.
+    pub async fn from_cache(
+        cache: &[u8],
+        resource_loader: ResourceLoader,
+    ) -> Result<PotreePointCloud, CacheError> {
+        let cache: HierarchyCache = Cursor::new(cache).read_le()?;
+        if cache.version != CACHE_VERSION {
+            return Err(CacheError::UnsupportedVersion(cache.version));
+        }
+
+        let layout = match cache.layout {
+            CachedLayout::Potree2 {
+                hierarchy_url,
+                octree_url,
+            } => Layout::Potree2 {
+                hierarchy_url: hierarchy_url.to_string_lossy(),
+                octree_url: octree_url.to_string_lossy(),
+            },
+            CachedLayout::Legacy {
+                hierarchy_step_size: 0,
+                ..
+            } => {
+                return Err(CacheError::InvalidLayout(
+                    "hierarchy step size 0".to_string(),
+                ));
+            }
+            CachedLayout::Legacy {
+                octree_url,
+                hierarchy_step_size,
+                extension,
+            } => Layout::Legacy(LegacyLayout {
+                octree_url: octree_url.to_string_lossy(),
+                hierarchy_step_size,
+                extension: match extension.to_string_lossy().as_str() {
+                    "bin" => "bin",
+                    "las" => "las",
+                    "laz" => "laz",
+                    extension => return Err(CacheError::InvalidLayout(extension.to_string())),
+                },
+            }),
+        };
+
+        let mut this = Self {
+            metadata: serde_json::from_slice(&cache.metadata.bytes)?,
+            layout,
+            octree: FlatOctree::new(),
+            names: HashMap::new(),
+            version: 0,
+            changes: HashMap::new(),
+            subscribers: Vec::new(),
+            resource_loader,
+        };
+
+        let source = this.resource_loader.head(&this.hierarchy_source()).await?;
+        if !cache::is_up_to_date(&(&cache.source).into(), &source) {
+            return Err(CacheError::Stale);
+        }
+
+        let root_id = this.octree.root_id();
+        let root = this.octree.root_mut();
+        *root = this.metadata.create_root_node();
+        root.id = Some(root_id);
+
+        cache::restore_nodes(&mut this.octree, cache.nodes)?;
+        this.subtree_changed(root_id);
+
+        Ok(this)
+    }
+
+    /// Load a Potree point cloud from a cache written by [`Self::to_cache`] if it is provided,
+    /// valid and up to date, from its url otherwise.
+    pub async fn from_url_cached(
+        url: &str,
+        cache: Option<&[u8]>,
+        resource_loader: ResourceLoader,
+    ) -> Result<PotreePointCloud, LoadPotreePointCloudError> {
+        if let Some(cache) = cache {
+            match Self::from_cache(cache, resource_loader.clone()).await {
+                Ok(this) if this.hierarchy_source() == format!("{}/hierarchy.bin", url) => {
+                    return Ok(this);
+                }
+                Ok(_) => debug!("Ignoring cache of another point cloud"),
+                Err(error) => debug!("Ignoring hierarchy cache: {}", error),
+            }
+        }
+
+        Self::from_url(url, resource_loader).await
+    }
+
+    // Resource whose changes invalidate a cache, the root `.hrc` file of legacy point clouds
+    fn hierarchy_source(&self) -> String {
+        match &self.layout {
+            Layout::Potree2 { hierarchy_url, .. } => hierarchy_url.clone(),
+            Layout::Legacy(layout) => layout.hierarchy_url("r"),
+        }
+    }
+
+    /// Load every hierarchy chunk not loaded yet, fetching up to
+    /// [`DEFAULT_MAX_CONCURRENT_REQUESTS`] chunks at the same time.
     pub async fn load_entire_hierarchy(&mut self) -> Result<(), ReadHierarchyError> {
-        // get the root node
-        let root = self.octree.root();
-        let children = root.children.clone();
+        self.load_entire_hierarchy_with(DEFAULT_MAX_CONCURRENT_REQUESTS)
+            .await
+    }
 
-        for child in children {
-            self.parse_entire_hierarchy(child).await?;
+    /// Load every hierarchy chunk not loaded yet, fetching up to `max_concurrent_requests`
+    /// chunks at the same time.
+    ///
+    /// Chunks are parsed as soon as they are received, and the proxies they contain are
+    /// queued right away, so requests are not limited to one level of the hierarchy at a time.
+    pub async fn load_entire_hierarchy_with(
+        &mut self,
+        max_concurrent_requests: usize,
+    ) -> Result<(), ReadHierarchyError> {
+        // in flight requests borrow their own loader, leaving the octree free to update
+        let resource_loader = self.resource_loader.clone();
+        let mut pending = self.proxies_from(self.octree.root_id());
+        let mut in_flight = FuturesUnordered::new();
+
+        loop {
+            while in_flight.len() < max_concurrent_requests.max(1)
+                && let Some(node_id) = pending.pop()
+            {
+                let node = self
+                    .octree
+                    .node(node_id)
+                    .expect("missing node in hierarchy, shouldn't happen");
+                let request = self.hierarchy_request(node);
+                let resource_loader = &resource_loader;
+
+                in_flight.push(async move { (node_id, request.fetch(resource_loader).await) });
+            }
+
+            let Some((node_id, data)) = in_flight.next().await else {
+                break;
+            };
+
+            self.parse_hierarchy_chunk(node_id, &data?)?;
+            pending.extend(self.proxies_from(node_id));
         }
 
         Ok(())
     }
 
-    async fn parse_entire_hierarchy(&mut self, node_id: NodeId) -> Result<(), ReadHierarchyError> {
-        // load the node's hierarchy
-        self.load_hierarchy(node_id).await?;
+    // Proxies in the subtree of `node_id`, including itself
+    fn proxies_from(&self, node_id: NodeId) -> Vec<NodeId> {
+        self.octree
+            .depth_first(node_id)
+            .filter(|(_, node)| node.node_type == 2)
+            .map(|(node_id, _)| node_id)
+            .collect()
+    }
 
-        // get the node's children
-        let node = self
-            .octree
-            .node(node_id)
-            .expect("parse_entire_hierarchy: invalid node_id");
-        let children = node.children.clone();
+    fn hierarchy_request(&self, node: &OctreeNode) -> HierarchyRequest {
+        match &self.layout {
+            Layout::Potree2 { hierarchy_url, .. } => HierarchyRequest {
+                url: hierarchy_url.clone(),
+                range: Some((
+                    node.hierarchy_byte_offset,
+                    node.hierarchy_byte_size as usize,
+                )),
+            },
+            Layout::Legacy(layout) => HierarchyRequest {
+                url: layout.hierarchy_url(&node.name),
+                range: None,
+            },
+        }
+    }
 
-        // load childrens hierarchy
-        for child in children {
-            Box::pin(self.parse_entire_hierarchy(child)).await?;
+    pub(crate) fn parse_hierarchy_chunk(
+        &mut self,
+        node_id: NodeId,
+        buf: &[u8],
+    ) -> Result<(), ReadHierarchyError> {
+        match &self.layout {
+            Layout::Potree2 { .. } => self.parse_hierarchy(node_id, buf)?,
+            Layout::Legacy(layout) => {
+                legacy::parse_hierarchy(&mut self.octree, node_id, buf, layout.hierarchy_step_size)?
+            }
         }
 
+        let version = self.subtree_changed(node_id);
+        self.emit(HierarchyEvent::Loaded {
+            node_id,
+            name: self.octree.node(node_id).unwrap().name.clone(),
+            version,
+        });
+
         Ok(())
     }
 
-    fn parse_hierarchy(&mut self, node_id: NodeId, buf: &[u8]) -> binrw::BinResult<()> {
+    // Indexes the nodes of the subtree of `node_id` by name, and records that it changed
+    fn subtree_changed(&mut self, node_id: NodeId) -> u64 {
+        self.version += 1;
+
+        for (current_id, node) in self.octree.depth_first(node_id) {
+            self.names.insert(node.name.clone(), current_id);
+        }
+
+        let name = self.octree.node(node_id).unwrap().name.clone();
+        // the changes of the descendants are part of this one
+        self.changes
+            .retain(|changed, _| !changed.starts_with(name.as_str()));
+        self.changes.insert(name, self.version);
+
+        self.version
+    }
+
+    fn emit(&mut self, event: HierarchyEvent) {
+        // forget the subscribers whose receiver has been dropped
+        self.subscribers
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+
+    /// Returns a stream of the changes of the loaded hierarchy.
+    ///
+    /// Events are buffered until they are received, the stream ends when the point cloud is dropped.
+    /// Clones of the point cloud start without subscribers.
+    pub fn subscribe(&mut self) -> UnboundedReceiver<HierarchyEvent> {
+        let (sender, receiver) = unbounded();
+        self.subscribers.push(sender);
+
+        receiver
+    }
+
+    /// Current version of the loaded hierarchy, incremented each time a part of it
+    /// is loaded or unloaded.
+    pub fn hierarchy_version(&self) -> u64 {
+        self.version
+    }
+
+    /// Snapshots of the parts of the loaded hierarchy which changed since `version`.
+    ///
+    /// A delta since version 0 holds the whole loaded hierarchy. The delta can be applied
+    /// to another point cloud with [`Self::apply_hierarchy_delta`].
+    pub fn hierarchy_delta(&self, version: u64) -> HierarchyDelta {
+        let changed: BTreeSet<&str> = self
+            .changes
+            .iter()
+            .filter(|(_, change_version)| **change_version > version)
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        // names are sorted, ancestors come before their descendants
+        let mut roots: Vec<&str> = Vec::new();
+        for name in changed {
+            if !roots.iter().any(|root| name.starts_with(root)) {
+                roots.push(name);
+            }
+        }
+
+        let subtrees = roots
+            .into_iter()
+            .filter_map(|name| self.octree.node(self.node_id_by_name(name)?))
+            .map(|node| self.hierarchy_snaphot_from_node(node))
+            .collect();
+
+        HierarchyDelta {
+            version: self.version,
+            subtrees,
+        }
+    }
+
+    /// Applies a delta produced by [`Self::hierarchy_delta`] on another instance of
+    /// the same point cloud.
+    pub fn apply_hierarchy_delta(
+        &mut self,
+        delta: &HierarchyDelta,
+    ) -> Result<(), ReadHierarchyError> {
+        for subtree in &delta.subtrees {
+            let name = &subtree
+                .first()
+                .ok_or_else(|| ReadHierarchyError::InvalidSnapshot("empty snapshot".to_string()))?
+                .name;
+            let node_id = self
+                .node_id_by_name(name)
+                .ok_or(ReadHierarchyError::NodeNotFound)?;
+
+            for removed in self.octree.replace_with_snapshot(node_id, subtree)? {
+                self.names.remove(&removed.name);
+            }
+
+            let version = self.subtree_changed(node_id);
+            self.emit(HierarchyEvent::Loaded {
+                node_id,
+                name: name.clone(),
+                version,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the id of the node named `name` (such as `r0413`), if it is loaded.
+    pub fn node_id_by_name(&self, name: &str) -> Option<NodeId> {
+        self.names.get(name).copied()
+    }
+
+    /// Returns the id of the node at `key`, if it is loaded.
+    pub fn node_id_by_key(&self, key: &OctreeKey) -> Option<NodeId> {
+        self.node_id_by_name(&key.to_name())
+    }
+
+    /// Returns the id of the node named `name` (such as `r0413`), loading the hierarchy
+    /// chunks of the proxies on its path if needed.
+    ///
+    /// Returns `None` if the node does not exist.
+    pub async fn node_by_name(&mut self, name: &str) -> Result<Option<NodeId>, ReadHierarchyError> {
+        let key = OctreeKey::from_name(name)
+            .ok_or_else(|| ReadHierarchyError::InvalidNodeName(name.to_string()))?;
+
+        self.node_by_key(&key).await
+    }
+
+    /// Returns the id of the node at `key`, loading the hierarchy chunks of the proxies
+    /// on its path if needed.
+    ///
+    /// Returns `None` if the node does not exist.
+    pub async fn node_by_key(
+        &mut self,
+        key: &OctreeKey,
+    ) -> Result<Option<NodeId>, ReadHierarchyError> {
+        let name = key.to_name();
+        if let Some(node_id) = self.node_id_by_name(&name) {
+            return Ok(Some(node_id));
+        }
+
+        // walk down from the root, one level at a time
+        let mut current_id = self.octree.root_id();
+        for level in 1..name.len() {
+            self.load_hierarchy(current_id).await?;
+
+            match self.node_id_by_name(&name[..=level]) {
+                Some(child_id) => current_id = child_id,
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(current_id))
+    }
+
+    /// Walk the octree breadth first from the root, descending into each node
+    /// until `stop` returns true for it.
+    ///
+    /// Proxy nodes the traversal descends into have their hierarchy chunk loaded first,
+    /// so `stop` is only ever called on nodes whose parent is fully resolved.
+    /// Returns the visited nodes, parents first.
+    pub async fn expand_until<F>(&mut self, stop: F) -> Result<Vec<NodeId>, ReadHierarchyError>
+    where
+        F: FnMut(&OctreeNode) -> bool,
+    {
+        self.expand_from_until(self.octree.root_id(), stop).await
+    }
+
+    /// Same as [`Self::expand_until`], starting from `node_id`.
+    pub async fn expand_from_until<F>(
+        &mut self,
+        node_id: NodeId,
+        mut stop: F,
+    ) -> Result<Vec<NodeId>, ReadHierarchyError>
+    where
+        F: FnMut(&OctreeNode) -> bool,
+    {
+        let mut visited = Vec::new();
+        let mut queue = VecDeque::from([node_id]);
+
+        while let Some(current_id) = queue.pop_front() {
+            let node = self
+                .octree
+                .node(current_id)
+                .ok_or(ReadHierarchyError::NodeNotFound)?;
+
+            visited.push(current_id);
+            if stop(node) {
+                continue;
+            }
+
+            // resolve the children of proxies before visiting them
+            if node.node_type == 2 {
+                self.load_hierarchy(current_id).await?;
+            }
+
+            let node = self
+                .octree
+                .node(current_id)
+                .expect("missing node in hierarchy, shouldn't happen");
+            queue.extend(node.children.iter().copied());
+        }
+
+        Ok(visited)
+    }
+
+    fn parse_hierarchy(&mut self, node_id: NodeId, buf: &[u8]) -> Result<(), ReadHierarchyError> {
         const BYTES_PER_NODE: usize = 22;
+
+        let chunk_root = self
+            .octree
+            .node(node_id)
+            .ok_or(ReadHierarchyError::NodeNotFound)?;
+
+        if buf.is_empty() || !buf.len().is_multiple_of(BYTES_PER_NODE) {
+            return Err(ReadHierarchyError::InvalidHierarchySize {
+                node: chunk_root.name.clone(),
+                offset: chunk_root.hierarchy_byte_offset,
+                size: buf.len(),
+            });
+        }
+
         let mut cursor = Cursor::new(buf);
-        let num_nodes = buf.len() / BYTES_PER_NODE;
+        let num_entries = buf.len() / BYTES_PER_NODE;
+        let entries = (0..num_entries)
+            .map(|_| cursor.read_le())
+            .collect::<Result<Vec<HierarchyNodeEntry>, _>>()?;
+
+        // check that every child announced by a child mask has an entry,
+        // before modifying the octree
+        let mut num_nodes = 1;
+        for (i, entry) in entries.iter().enumerate() {
+            if i >= num_nodes {
+                // remaining entries are not referenced by any node
+                break;
+            }
+            if entry.r#type == 2 {
+                continue;
+            }
+
+            num_nodes += entry.child_mask.count_ones() as usize;
+            if num_nodes > num_entries {
+                return Err(ReadHierarchyError::InvalidChildMask {
+                    node: chunk_root.name.clone(),
+                    entry: i,
+                    required: num_nodes,
+                    available: num_entries,
+                });
+            }
+        }
 
         // reserve additional nodes
         self.octree.reserve(num_nodes - 1);
@@ -192,12 +965,10 @@ impl PotreePointCloud {
         let mut node_pos = 1;
 
         // the first node is always the root of the (sub-)hierarchy we are loading
-        for i in 0..num_nodes {
+        for (i, header) in entries.into_iter().take(num_nodes).enumerate() {
             let current_id = node_ids[i];
             let current = self.octree.node_mut(current_id).unwrap();
 
-            let header: HierarchyNodeEntry = cursor.read_le()?;
-
             if current.node_type == 2 {
                 current.byte_offset = header.byte_offset;
                 current.byte_size = header.byte_size;
@@ -250,7 +1021,7 @@ impl PotreePointCloud {
                 child
                     .name
                     .push_str(&format!("{}{}", current_name, child_index));
-                child.bounding_box = create_child_aabb(&current_bounding_box, child_index);
+                child.bounding_box = current_bounding_box.child(child_index);
                 child.spacing = current_spacing / 2.0;
                 child.level = current_level + 1;
                 child.parent = Some(current_id);
@@ -313,176 +1084,338 @@ impl PotreePointCloud {
 
     // Functions to load points
     pub async fn load_points(&self, node_id: NodeId) -> Result<Vec<PointData>, LoadPointsError> {
-        let node = self
-            .octree
-            .node(node_id)
-            .ok_or(LoadPointsError::NodeNotFound)?;
-
-        self.load_points_for_node(node).await
+        Ok(self.load_point_buffer(node_id).await?.to_point_data())
     }
 
     pub async fn load_points_for_node(
         &self,
         node: &OctreeNode,
     ) -> Result<Vec<PointData>, LoadPointsError> {
-        let buffer = self
-            .resource_loader
-            .get_range(
-                &self.octree_url,
-                node.byte_offset,
-                node.byte_size as usize,
-                None,
-            )
-            .await?;
-
-        let points = match self.metadata.encoding.as_str() {
-            "BROTLI" => self.parse_points_brotli(node, &buffer)?,
-            _ => {
-                return Err(LoadPointsError::EncodingUnimplemented(
-                    self.metadata.encoding.clone(),
-                ));
-            }
-        };
+        Ok(self.load_point_buffer_for_node(node).await?.to_point_data())
+    }
 
-        Ok(points)
+    /// Load the points of a node with every attribute decoded as a typed column.
+    pub async fn load_point_buffer(&self, node_id: NodeId) -> Result<PointBuffer, LoadPointsError> {
+        self.load_point_buffer_with(node_id, &AttributeSelection::All)
+            .await
     }
 
-    fn parse_points_brotli(
+    pub async fn load_point_buffer_for_node(
         &self,
         node: &OctreeNode,
-        buffer: &[u8],
-    ) -> Result<Vec<PointData>, LoadPointsError> {
-        let mut cursor = Cursor::new(buffer);
-        let mut input = brotli_decompressor::Decompressor::new(&mut cursor, 4096);
-        let mut decompressed_buffer = Vec::new();
-        let size = input.read_to_end(&mut decompressed_buffer)?;
-
-        let mut byte_offset: usize = 0;
-
-        let mut points = vec![PointData::default(); node.num_points as usize];
-
-        for point_attribute in &self.metadata.attributes {
-            let point_data = PointData::default();
-            points.push(point_data);
+    ) -> Result<PointBuffer, LoadPointsError> {
+        self.load_point_buffer_for_node_with(node, &AttributeSelection::All)
+            .await
+    }
 
-            match point_attribute.name.as_str() {
-                "POSITION_CARTESIAN" | "position" => {
-                    let scale = &self.metadata.scale;
-                    let offset = &self.metadata.offset;
+    /// Load the points of a node, decoding only the selected attributes.
+    pub async fn load_point_buffer_with(
+        &self,
+        node_id: NodeId,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, LoadPointsError> {
+        let node = self
+            .octree
+            .node(node_id)
+            .ok_or(LoadPointsError::NodeNotFound)?;
 
-                    for j in 0..node.num_points {
-                        let bytes = &decompressed_buffer[byte_offset..byte_offset + 16];
-                        let (x, y, z) = read_morton_128(bytes);
+        self.load_point_buffer_for_node_with(node, selection).await
+    }
 
-                        points[j as usize].position = node.bounding_box.min
-                            + DVec3::new(
-                                x as f64 * scale[0] + offset[0] - node.bounding_box.min.x,
-                                y as f64 * scale[1] + offset[1] - node.bounding_box.min.y,
-                                z as f64 * scale[2] + offset[2] - node.bounding_box.min.z,
-                            );
+    pub async fn load_point_buffer_for_node_with(
+        &self,
+        node: &OctreeNode,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, LoadPointsError> {
+        let buffer = match &self.layout {
+            // nothing to fetch for empty nodes
+            Layout::Potree2 { .. } if node.byte_size == 0 => {
+                return Ok(empty_points(&self.metadata, selection));
+            }
+            Layout::Legacy(_) if node.num_points == 0 => {
+                return Ok(empty_points(&self.metadata, selection));
+            }
+            Layout::Potree2 { octree_url, .. } => {
+                self.resource_loader
+                    .get_range(octree_url, node.byte_offset, node.byte_size as usize, None)
+                    .await?
+            }
+            Layout::Legacy(layout) => {
+                self.resource_loader
+                    .get(&layout.node_url(&node.name), None)
+                    .await?
+            }
+        };
 
-                        byte_offset += 16;
-                    }
-                }
-                "RGBA" | "rgba" | "RGB" | "rgb" => {
-                    for j in 0..node.num_points {
-                        let bytes = &decompressed_buffer[byte_offset..byte_offset + 8];
-                        let (r, g, b) = read_morton_64(bytes);
-
-                        points[j as usize].color = U8Vec3::new(
-                            if r > 255 { r / 256 } else { r } as u8,
-                            if g > 255 { g / 256 } else { g } as u8,
-                            if b > 255 { b / 256 } else { b } as u8,
-                        );
-
-                        byte_offset += 8;
-                    }
-                }
-                _ => {
-                    for j in 0..node.num_points {
-                        let bytes = &decompressed_buffer
-                            [byte_offset..byte_offset + point_attribute.size as usize];
+        decode_points(&self.metadata, node, &buffer, selection)
+    }
 
-                        byte_offset += point_attribute.size as usize;
-                    }
-                }
-            }
-        }
+    /// Load the points of a node as vertex buffers, decoding only the selected attributes.
+    /// Positions are relative to `origin`, or to the node's bounding box minimum if `None`.
+    pub async fn load_gpu_buffer(
+        &self,
+        node_id: NodeId,
+        origin: Option<DVec3>,
+        selection: &AttributeSelection,
+    ) -> Result<GpuPointBuffer, LoadPointsError> {
+        let node = self
+            .octree
+            .node(node_id)
+            .ok_or(LoadPointsError::NodeNotFound)?;
 
-        // println!("Final offset: {}, size: {}", byte_offset, size);
+        let points = self
+            .load_point_buffer_for_node_with(node, selection)
+            .await?;
 
-        Ok(points)
+        Ok(points.into_gpu_buffer(origin.unwrap_or(node.bounding_box.min)))
     }
 
     // Functions to access the octree
     pub fn octree(&self) -> &FlatOctree<OctreeNode> {
         &self.octree
     }
-}
-
-fn read_morton_64(bytes: &[u8]) -> (u16, u16, u16) {
-    let mc_0 = LittleEndian::read_u32(&bytes[4..8]);
-    let mc_1 = LittleEndian::read_u32(&bytes[0..4]);
 
-    decode_morton_64(mc_0, mc_1)
-}
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
 
-fn read_morton_128(bytes: &[u8]) -> (u32, u32, u32) {
-    let mc_0 = LittleEndian::read_u32(&bytes[4..8]);
-    let mc_1 = LittleEndian::read_u32(&bytes[0..4]);
-    let mc_2 = LittleEndian::read_u32(&bytes[12..16]);
-    let mc_3 = LittleEndian::read_u32(&bytes[8..12]);
+    /// Returns the loaded nodes intersecting the frustum of the provided view-projection matrix.
+    /// See [`FlatOctree::frustum_cull`].
+    pub fn frustum_cull(&self, view_projection: DMat4) -> FrustumCulling {
+        self.octree.frustum_cull(view_projection)
+    }
 
-    decode_morton_128(mc_0, mc_1, mc_2, mc_3)
+    /// Returns the loaded nodes to render, by priority, within the point budget.
+    /// See [`FlatOctree::select_nodes`].
+    pub fn select_nodes(&self, options: &LodOptions) -> LodSelection {
+        self.octree.select_nodes(options)
+    }
 }
 
-fn dealign_24b(mut morton: u32) -> u32 {
-    // Garde seulement chaque 3ème bit
-    morton &= 0x09249249; // 0b001001001001001001001001001001
-
-    morton = (morton | (morton >> 2)) & 0x030c30c3;
-    morton = (morton | (morton >> 4)) & 0x0300f00f;
-    morton = (morton | (morton >> 8)) & 0x030000ff;
-    morton = (morton | (morton >> 16)) & 0x000003ff;
-
-    morton
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{BoundingBox, HierarchyMetadata};
+    use crate::writer::{WrittenNode, encode_hierarchy};
+    use std::collections::BTreeMap;
+
+    // r03 and r70 have children, they are proxies of the first hierarchy chunk
+    const NAMES: [&str; 9] = ["r", "r0", "r03", "r036", "r0361", "r2", "r7", "r70", "r701"];
+
+    // A point cloud whose first hierarchy chunk is loaded, and its encoded hierarchy
+    fn point_cloud() -> (PotreePointCloud, Vec<u8>) {
+        let nodes: BTreeMap<String, WrittenNode> = NAMES
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let node = WrittenNode {
+                    num_points: i as u32 + 1,
+                    byte_offset: i as u64 * 100,
+                    byte_size: i as u64 + 10,
+                };
+                (name.to_string(), node)
+            })
+            .collect();
+        let (hierarchy, first_chunk_size) = encode_hierarchy(&nodes, 2).unwrap();
+
+        let metadata = Metadata {
+            version: "2.0".to_string(),
+            name: String::new(),
+            description: String::new(),
+            points: 0,
+            projection: String::new(),
+            hierarchy: HierarchyMetadata {
+                first_chunk_size,
+                step_size: 2,
+                depth: 0,
+            },
+            offset: [0.0; 3],
+            scale: [0.001; 3],
+            spacing: 1.0,
+            bounding_box: BoundingBox {
+                min: [0.0; 3],
+                max: [8.0; 3],
+            },
+            encoding: "DEFAULT".to_string(),
+            attributes: Vec::new(),
+        };
+        let root = OctreeNodeSnapshot::from(&metadata.create_root_node());
+        let mut point_cloud =
+            PotreePointCloud::from_snapshot("", metadata, &[root], ResourceLoader::new()).unwrap();
+        load_chunk(&mut point_cloud, "r", &hierarchy);
 
-fn decode_morton_64(mc_0: u32, mc_1: u32) -> (u16, u16, u16) {
-    let r = dealign_24b((mc_1 & 0x00FFFFFF) >> 0)
-        | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 0) << 8);
+        (point_cloud, hierarchy)
+    }
 
-    let g = dealign_24b((mc_1 & 0x00FFFFFF) >> 1)
-        | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 1) << 8);
+    fn load_chunk(point_cloud: &mut PotreePointCloud, name: &str, hierarchy: &[u8]) -> NodeId {
+        let node_id = point_cloud.node_id_by_name(name).unwrap();
+        let node = point_cloud.octree().node(node_id).unwrap();
+        let start = node.hierarchy_byte_offset as usize;
+        let chunk = hierarchy[start..start + node.hierarchy_byte_size as usize].to_vec();
+        point_cloud.parse_hierarchy_chunk(node_id, &chunk).unwrap();
 
-    let b = dealign_24b((mc_1 & 0x00FFFFFF) >> 2)
-        | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 2) << 8);
+        node_id
+    }
 
-    (r as u16, g as u16, b as u16)
-}
+    // The loaded nodes by name, with their type, byte range and children names
+    fn loaded_nodes(
+        point_cloud: &PotreePointCloud,
+    ) -> BTreeMap<String, (u8, u64, u64, Vec<String>)> {
+        let octree = point_cloud.octree();
 
-fn decode_morton_128(mc_0: u32, mc_1: u32, mc_2: u32, mc_3: u32) -> (u32, u32, u32) {
-    // First part (lower bits)
-    let mut x = dealign_24b((mc_3 & 0x00FFFFFF) >> 0)
-        | (dealign_24b(((mc_3 >> 24) | (mc_2 << 8)) >> 0) << 8);
+        octree
+            .depth_first(octree.root_id())
+            .map(|(_, node)| {
+                let children = node
+                    .children
+                    .iter()
+                    .map(|child| octree.node(*child).unwrap().name.clone())
+                    .collect();
+                let summary = (node.node_type, node.byte_offset, node.byte_size, children);
+                (node.name.clone(), summary)
+            })
+            .collect()
+    }
 
-    let mut y = dealign_24b((mc_3 & 0x00FFFFFF) >> 1)
-        | (dealign_24b(((mc_3 >> 24) | (mc_2 << 8)) >> 1) << 8);
+    #[test]
+    fn delta_applied_to_clone_gives_same_hierarchy() {
+        let (mut point_cloud, hierarchy) = point_cloud();
+        let version = point_cloud.hierarchy_version();
+        let mut clone = point_cloud.clone();
+
+        load_chunk(&mut point_cloud, "r03", &hierarchy);
+        load_chunk(&mut point_cloud, "r70", &hierarchy);
+
+        let delta = point_cloud.hierarchy_delta(version);
+        let roots: Vec<_> = delta
+            .subtrees
+            .iter()
+            .map(|subtree| subtree[0].name.as_str())
+            .collect();
+        assert_eq!(roots, ["r03", "r70"]);
+        assert_eq!(delta.version, point_cloud.hierarchy_version());
+
+        clone.apply_hierarchy_delta(&delta).unwrap();
+
+        assert_eq!(loaded_nodes(&clone), loaded_nodes(&point_cloud));
+        assert_eq!(loaded_nodes(&clone).len(), NAMES.len());
+        for name in NAMES {
+            assert!(clone.node_id_by_name(name).is_some(), "{name}");
+        }
 
-    let mut z = dealign_24b((mc_3 & 0x00FFFFFF) >> 2)
-        | (dealign_24b(((mc_3 >> 24) | (mc_2 << 8)) >> 2) << 8);
+        // nothing changed since the last version
+        assert!(
+            point_cloud
+                .hierarchy_delta(point_cloud.hierarchy_version())
+                .subtrees
+                .is_empty()
+        );
+    }
 
-    // Second part (upper bits) - only if needed
-    if mc_1 != 0 || mc_2 != 0 {
-        x |= (dealign_24b((mc_1 & 0x00FFFFFF) >> 0) << 16)
-            | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 0) << 24);
+    #[test]
+    fn changes_covered_by_ancestors_are_forgotten() {
+        let (mut point_cloud, hierarchy) = point_cloud();
+        let whole = point_cloud.hierarchy_delta(0);
+
+        load_chunk(&mut point_cloud, "r03", &hierarchy);
+        load_chunk(&mut point_cloud, "r70", &hierarchy);
+        let mut changed: Vec<_> = point_cloud.changes.keys().cloned().collect();
+        changed.sort();
+        assert_eq!(changed, ["r", "r03", "r70"]);
+
+        // replacing the whole hierarchy supersedes the changes below the root
+        let version = point_cloud.hierarchy_version();
+        point_cloud.apply_hierarchy_delta(&whole).unwrap();
+        assert_eq!(point_cloud.changes.len(), 1);
+        assert_eq!(point_cloud.changes["r"], version + 1);
+
+        let delta = point_cloud.hierarchy_delta(version);
+        assert_eq!(delta.subtrees.len(), 1);
+        assert_eq!(delta.subtrees[0][0].name, "r");
+        // the proxies of the first chunk are back
+        assert!(point_cloud.node_id_by_name("r036").is_none());
+    }
 
-        y |= (dealign_24b((mc_1 & 0x00FFFFFF) >> 1) << 16)
-            | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 1) << 24);
+    #[test]
+    fn unload_hierarchy_turns_chunk_root_into_proxy() {
+        let (mut point_cloud, hierarchy) = point_cloud();
+        let r03 = load_chunk(&mut point_cloud, "r03", &hierarchy);
+        let r036 = point_cloud.node_id_by_name("r036").unwrap();
+        let r0361 = point_cloud.node_id_by_name("r0361").unwrap();
+
+        // only the roots of hierarchy chunks can be unloaded
+        let r0 = point_cloud.node_id_by_name("r0").unwrap();
+        assert!(matches!(
+            point_cloud.unload_hierarchy(r0),
+            Err(ReadHierarchyError::NotAChunkRoot(name)) if name == "r0"
+        ));
+
+        point_cloud.unload_hierarchy(r03).unwrap();
+
+        let node = point_cloud.octree().node(r03).unwrap();
+        assert_eq!(node.node_type, 2);
+        assert!(node.children.is_empty());
+        // r701, in the chunk of r70, is not loaded either
+        assert_eq!(point_cloud.octree().len(), NAMES.len() - 3);
+        for (name, node_id) in [("r036", r036), ("r0361", r0361)] {
+            assert!(point_cloud.node_id_by_name(name).is_none(), "{name}");
+            assert!(point_cloud.octree().node(node_id).is_none(), "{name}");
+        }
 
-        z |= (dealign_24b((mc_1 & 0x00FFFFFF) >> 2) << 16)
-            | (dealign_24b(((mc_1 >> 24) | (mc_0 << 8)) >> 2) << 24);
+        // the freed slots are reused by the reloaded nodes, with a new generation
+        load_chunk(&mut point_cloud, "r03", &hierarchy);
+        let reloaded = point_cloud.node_id_by_name("r036").unwrap();
+        assert_ne!(reloaded, r036);
+        assert!(point_cloud.octree().node(reloaded).is_some());
+        assert!(point_cloud.octree().node(r036).is_none());
+        assert!(point_cloud.octree().node(r0361).is_none());
+        assert_eq!(point_cloud.octree().len(), NAMES.len() - 1);
     }
 
-    (x, y, z)
+    #[test]
+    fn subscribers_receive_hierarchy_events() {
+        let (mut point_cloud, hierarchy) = point_cloud();
+        let mut events = point_cloud.subscribe();
+
+        let r03 = load_chunk(&mut point_cloud, "r03", &hierarchy);
+        point_cloud.unload_hierarchy(r03).unwrap();
+        let delta = point_cloud.hierarchy_delta(0);
+        let mut clone = point_cloud.clone();
+        let mut clone_events = clone.subscribe();
+        clone.apply_hierarchy_delta(&delta).unwrap();
+
+        let version = point_cloud.hierarchy_version();
+        match events.try_next().unwrap().unwrap() {
+            HierarchyEvent::Loaded {
+                node_id,
+                name,
+                version: loaded_version,
+            } => {
+                assert_eq!((node_id, name.as_str()), (r03, "r03"));
+                assert_eq!(loaded_version, version - 1);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        match events.try_next().unwrap().unwrap() {
+            HierarchyEvent::Unloaded {
+                node_id,
+                name,
+                version: unloaded_version,
+            } => {
+                assert_eq!((node_id, name.as_str()), (r03, "r03"));
+                assert_eq!(unloaded_version, version);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        // the events of the clone are not sent to the subscribers of the original
+        assert!(events.try_next().is_err());
+        assert!(matches!(
+            clone_events.try_next().unwrap().unwrap(),
+            HierarchyEvent::Loaded { name, .. } if name == "r"
+        ));
+        assert!(clone_events.try_next().is_err());
+
+        drop(point_cloud);
+        assert!(matches!(events.try_next(), Ok(None)));
+    }
 }
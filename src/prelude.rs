@@ -1,9 +1,30 @@
 pub use crate::resource::ResourceLoader;
 pub use crate::point_cloud::PotreePointCloud;
-pub use crate::octree::snapshot::OctreeNodeSnapshot;
+pub use crate::octree::snapshot::{HierarchyDelta, OctreeNodeSnapshot};
+pub use crate::point_cloud::HierarchyEvent;
+pub use crate::octree::key::OctreeKey;
+pub use crate::octree::region::{PolygonPrism, Region, Sphere};
+pub use crate::query::QueryDepth;
+pub use crate::pick::PickHit;
+pub use crate::profile::{Profile, ProfilePoints};
+pub use crate::writer::{PotreeDataset, PotreeWriter};
+pub use crate::export::ExportFormat;
+#[cfg(feature = "fs")]
+pub use crate::converter::{ConverterOptions, PotreeConverter};
 pub use crate::point::PointData;
+pub use crate::point::buffer::{AttributeBuffer, AttributeData, AttributeSelection, PointBuffer};
+pub use crate::point::gpu::GpuPointBuffer;
 
 // Error types
 pub use crate::point_cloud::LoadPotreePointCloudError;
 pub use crate::point_cloud::ReadHierarchyError;
 pub use crate::point_cloud::LoadPointsError;
+pub use crate::point_cloud::CacheError;
+pub use crate::query::QueryError;
+pub use crate::writer::WriteDatasetError;
+#[cfg(feature = "fs")]
+pub use crate::converter::ConvertError;
+pub use crate::export::ExportError;
+pub use crate::point::buffer::AppendError;
+#[cfg(feature = "las")]
+pub use crate::import::LasReader;
@@ -0,0 +1,307 @@
+//! Elevation profiles: the points in a corridor along a polyline, projected on the
+//! vertical surface following the polyline.
+
+use crate::encoder::text::TextEncoder;
+use crate::metadata::{AttributeMetadata, AttributeType};
+use crate::octree::aabb::Aabb;
+use crate::octree::region::{Region, segments_intersect};
+use crate::point::buffer::{
+    AppendError, AttributeBuffer, AttributeData, AttributeSelection, PointBuffer,
+};
+use crate::point_cloud::PotreePointCloud;
+use crate::query::{QueryDepth, QueryError};
+use glam::{DVec2, DVec3};
+use std::io::Write;
+
+/// A corridor of `width` centered on a polyline drawn on the XY plane, unbounded in height.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub polyline: Vec<DVec2>,
+    pub width: f64,
+}
+
+/// Points of a profile, with their projection on the profile.
+#[derive(Clone, Debug, Default)]
+pub struct ProfilePoints {
+    /// The distance along the polyline and the height of each point
+    pub projected: Vec<DVec2>,
+    /// The points, with their world positions and their attributes
+    pub points: PointBuffer,
+    /// Scale of the point cloud, used to export the positions
+    pub scale: DVec3,
+    /// Offset of the point cloud, used to export the positions
+    pub offset: DVec3,
+}
+
+impl Profile {
+    pub fn new(polyline: Vec<DVec2>, width: f64) -> Self {
+        Self { polyline, width }
+    }
+
+    /// Length of the polyline.
+    pub fn length(&self) -> f64 {
+        self.segments()
+            .map(|(start, end)| start.distance(end))
+            .sum()
+    }
+
+    /// Returns the distance along the polyline and the height of the point,
+    /// or `None` if it is outside the corridor.
+    ///
+    /// A point next to a vertex, in the corridors of two segments, is projected on the first one.
+    pub fn project(&self, point: DVec3) -> Option<DVec2> {
+        let half_width = self.width * 0.5;
+        let position = point.truncate();
+
+        let mut distance = 0.0;
+        for (start, end) in self.segments() {
+            let length = start.distance(end);
+            let direction = (end - start).normalize_or_zero();
+
+            let along = (position - start).dot(direction);
+            let across = (position - start).perp_dot(direction);
+            if (0.0..=length).contains(&along) && across.abs() <= half_width {
+                return Some(DVec2::new(distance + along, point.z));
+            }
+
+            distance += length;
+        }
+
+        None
+    }
+
+    // Segments of the polyline, without the empty ones between repeated vertices
+    fn segments(&self) -> impl Iterator<Item = (DVec2, DVec2)> + '_ {
+        self.polyline
+            .windows(2)
+            .map(|segment| (segment[0], segment[1]))
+            .filter(|(start, end)| start.distance(*end) > f64::EPSILON)
+    }
+}
+
+impl Region for Profile {
+    fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let (min, max) = (aabb.min.truncate(), aabb.max.truncate());
+        let half_width = self.width * 0.5;
+
+        self.segments()
+            .any(|(start, end)| segment_rectangle_distance(start, end, min, max) <= half_width)
+    }
+
+    fn contains_point(&self, point: DVec3) -> bool {
+        self.project(point).is_some()
+    }
+}
+
+impl ProfilePoints {
+    /// Number of points in the profile.
+    pub fn len(&self) -> usize {
+        self.projected.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.projected.is_empty()
+    }
+
+    /// Appends the points of `other`, e.g. the points of a deeper level, which must hold
+    /// the same attributes.
+    pub fn append(&mut self, other: ProfilePoints) -> Result<(), AppendError> {
+        if self.is_empty() && self.points.attributes.is_empty() {
+            *self = other;
+            return Ok(());
+        }
+
+        self.points.append(other.points)?;
+        self.projected.extend(other.projected);
+
+        Ok(())
+    }
+
+    /// Writes the points as CSV, with a header line.
+    ///
+    /// The columns are the distance along the polyline, the height, the world position,
+    /// then one column per element of the attributes.
+    pub fn write_csv<W: Write>(&self, writer: W) -> std::io::Result<()> {
+        let projected = |name: &str, values: Vec<f64>| AttributeBuffer {
+            name: name.to_string(),
+            num_elements: 1,
+            data: AttributeData::Double(values),
+        };
+
+        let mut points = PointBuffer {
+            num_points: self.points.num_points,
+            positions: self.points.positions.clone(),
+            attributes: vec![
+                projected("distance", self.projected.iter().map(|p| p.x).collect()),
+                projected("height", self.projected.iter().map(|p| p.y).collect()),
+            ],
+        };
+        points
+            .attributes
+            .extend(self.points.attributes.iter().cloned());
+
+        let mut columns = vec![
+            AttributeMetadata::new("distance", AttributeType::Double, 1),
+            AttributeMetadata::new("height", AttributeType::Double, 1),
+            AttributeMetadata::new("position", AttributeType::Double, 3),
+        ];
+        // raw bytes of unknown attributes can't be written as values
+        columns.extend(
+            self.points
+                .attributes
+                .iter()
+                .filter(|attribute| !matches!(attribute.data, AttributeData::Undefined(_)))
+                .map(|attribute| {
+                    AttributeMetadata::new(
+                        &attribute.name,
+                        attribute.data.r#type(),
+                        attribute.num_elements,
+                    )
+                }),
+        );
+
+        let mut encoder = TextEncoder::new(writer, columns, ',', true)?;
+        encoder.write_points(&points)?;
+        encoder.finish()?;
+
+        Ok(())
+    }
+
+    /// Writes the points, with their world positions, as a `.las` file.
+    #[cfg(feature = "las")]
+    pub fn write_las<W>(&self, writer: W) -> Result<W, las::Error>
+    where
+        W: Write + std::io::Seek + Send + Sync + 'static,
+    {
+        crate::encoder::las::encode(&self.points, self.scale, self.offset, false, writer)
+    }
+
+    /// Writes the points, with their world positions, as a `.laz` file.
+    #[cfg(feature = "laz")]
+    pub fn write_laz<W>(&self, writer: W) -> Result<W, las::Error>
+    where
+        W: Write + std::io::Seek + Send + Sync + 'static,
+    {
+        crate::encoder::las::encode(&self.points, self.scale, self.offset, true, writer)
+    }
+}
+
+impl PotreePointCloud {
+    /// Returns the points of the profile, fetched down to `depth`.
+    ///
+    /// The hierarchy chunks of the proxies crossed by the corridor are loaded on the way.
+    /// Positions are always decoded, as they are needed to project the points.
+    pub async fn profile(
+        &mut self,
+        profile: &Profile,
+        depth: QueryDepth,
+        selection: &AttributeSelection,
+    ) -> Result<ProfilePoints, QueryError> {
+        let nodes = self.query_nodes(profile, depth).await?;
+        let points = self.load_points_in(nodes, profile, selection).await?;
+
+        Ok(self.project_profile(profile, points))
+    }
+
+    /// Returns the points of the profile held by the nodes of `level` only,
+    /// or `None` if the corridor crosses no node at this level.
+    ///
+    /// The nodes of different levels hold different points, so a profile is refined
+    /// progressively by appending the levels one after the other, from level 0
+    /// until `None` is returned.
+    pub async fn profile_level(
+        &mut self,
+        profile: &Profile,
+        level: u32,
+        selection: &AttributeSelection,
+    ) -> Result<Option<ProfilePoints>, QueryError> {
+        let nodes: Vec<_> = self
+            .query_nodes(profile, QueryDepth::MaxLevel(level))
+            .await?
+            .into_iter()
+            .filter(|node_id| {
+                self.octree()
+                    .node(*node_id)
+                    .is_some_and(|node| node.level == level)
+            })
+            .collect();
+
+        if nodes.is_empty() {
+            return Ok(None);
+        }
+
+        let points = self.load_points_in(nodes, profile, selection).await?;
+
+        Ok(Some(self.project_profile(profile, points)))
+    }
+
+    fn project_profile(&self, profile: &Profile, points: PointBuffer) -> ProfilePoints {
+        let metadata = self.metadata();
+
+        ProfilePoints {
+            projected: points
+                .positions
+                .iter()
+                .map(|position| profile.project(*position).unwrap_or_default())
+                .collect(),
+            points,
+            scale: DVec3::from_array(metadata.scale),
+            offset: DVec3::from_array(metadata.offset),
+        }
+    }
+}
+
+// Distance between a segment and a rectangle, 0 if they intersect
+fn segment_rectangle_distance(start: DVec2, end: DVec2, min: DVec2, max: DVec2) -> f64 {
+    let corners = [min, DVec2::new(max.x, min.y), max, DVec2::new(min.x, max.y)];
+    let in_rectangle = |point: DVec2| point.cmpge(min).all() && point.cmple(max).all();
+
+    if in_rectangle(start)
+        || in_rectangle(end)
+        || (0..4).any(|i| segments_intersect(start, end, corners[i], corners[(i + 1) % 4]))
+    {
+        return 0.0;
+    }
+
+    // otherwise, the nearest points are an end of the segment or a corner of the rectangle
+    let point_segment_distance = |point: DVec2| {
+        let segment = end - start;
+        let t = ((point - start).dot(segment) / segment.length_squared()).clamp(0.0, 1.0);
+        if t.is_nan() {
+            point.distance(start)
+        } else {
+            point.distance(start + segment * t)
+        }
+    };
+
+    [start, end]
+        .into_iter()
+        .map(|point| point.distance(point.clamp(min, max)))
+        .chain(corners.into_iter().map(point_segment_distance))
+        .fold(f64::INFINITY, f64::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_skips_repeated_vertices() {
+        let profile = Profile::new(vec![DVec2::ZERO, DVec2::ZERO, DVec2::new(1.0, 0.0)], 0.1);
+
+        assert_eq!(profile.length(), 1.0);
+        assert_eq!(profile.project(DVec3::new(100.0, 100.0, 5.0)), None);
+        assert_eq!(
+            profile.project(DVec3::new(0.5, 0.02, 5.0)),
+            Some(DVec2::new(0.5, 5.0))
+        );
+    }
+
+    #[test]
+    fn project_single_repeated_vertex() {
+        let profile = Profile::new(vec![DVec2::ONE, DVec2::ONE], 0.1);
+
+        assert_eq!(profile.project(DVec3::new(1.0, 1.0, 5.0)), None);
+        assert!(!profile.intersects_aabb(&Aabb::new(DVec3::ZERO, DVec3::splat(2.0))));
+    }
+}
@@ -0,0 +1,170 @@
+//! Queries returning the points of a point cloud inside a region of space.
+
+use crate::octree::NodeId;
+use crate::octree::aabb::Aabb;
+use crate::octree::node::OctreeNode;
+use crate::octree::region::{PolygonPrism, Region, Sphere};
+use crate::point::buffer::{AppendError, AttributeSelection, PointBuffer, is_position_attribute};
+use crate::point_cloud::{
+    DEFAULT_MAX_CONCURRENT_REQUESTS, LoadPointsError, PotreePointCloud, ReadHierarchyError,
+};
+use futures::StreamExt;
+use futures::stream;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("Error loading hierarchy: {0}")]
+    ReadHierarchyError(#[from] ReadHierarchyError),
+
+    #[error("Error loading points: {0}")]
+    LoadPointsError(#[from] LoadPointsError),
+
+    #[error("Error merging points: {0}")]
+    AppendError(#[from] AppendError),
+}
+
+/// How deep in the octree the points of a query are fetched.
+#[derive(Clone, Copy, Debug)]
+pub enum QueryDepth {
+    /// Fetch the nodes down to this level, the root being at level 0.
+    MaxLevel(u32),
+    /// Fetch the nodes whose spacing is at least this one, i.e. down to the first level
+    /// whose points are at most this far apart.
+    MinSpacing(f64),
+    /// Fetch every node intersecting the region.
+    Full,
+}
+
+impl QueryDepth {
+    /// Returns true if the node's points must be fetched.
+    pub fn includes(&self, node: &OctreeNode) -> bool {
+        match self {
+            QueryDepth::MaxLevel(level) => node.level <= *level,
+            QueryDepth::MinSpacing(spacing) => node.level == 0 || node.spacing * 2.0 > *spacing,
+            QueryDepth::Full => true,
+        }
+    }
+
+    /// Returns true if the children of the node must be visited.
+    pub fn includes_children(&self, node: &OctreeNode) -> bool {
+        match self {
+            QueryDepth::MaxLevel(level) => node.level < *level,
+            QueryDepth::MinSpacing(spacing) => node.spacing > *spacing,
+            QueryDepth::Full => true,
+        }
+    }
+}
+
+impl PotreePointCloud {
+    /// Returns the points inside the box, fetched down to `depth`.
+    pub async fn query_box(
+        &mut self,
+        aabb: &Aabb,
+        depth: QueryDepth,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, QueryError> {
+        self.query(aabb, depth, selection).await
+    }
+
+    /// Returns the points inside the sphere, fetched down to `depth`.
+    pub async fn query_sphere(
+        &mut self,
+        sphere: &Sphere,
+        depth: QueryDepth,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, QueryError> {
+        self.query(sphere, depth, selection).await
+    }
+
+    /// Returns the points inside the extruded polygon, fetched down to `depth`.
+    pub async fn query_polygon_prism(
+        &mut self,
+        prism: &PolygonPrism,
+        depth: QueryDepth,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, QueryError> {
+        self.query(prism, depth, selection).await
+    }
+
+    /// Returns the points inside the region, fetched down to `depth`.
+    ///
+    /// The hierarchy chunks of the intersecting proxies are loaded on the way.
+    /// Positions are always decoded, as they are needed to filter the points.
+    pub async fn query<R: Region>(
+        &mut self,
+        region: &R,
+        depth: QueryDepth,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, QueryError> {
+        let nodes = self.query_nodes(region, depth).await?;
+
+        self.load_points_in(nodes, region, selection).await
+    }
+
+    /// Returns the nodes intersecting the region down to `depth`, parents first,
+    /// loading the hierarchy chunks of the intersecting proxies on the way.
+    pub async fn query_nodes<R: Region>(
+        &mut self,
+        region: &R,
+        depth: QueryDepth,
+    ) -> Result<Vec<NodeId>, ReadHierarchyError> {
+        let visited = self
+            .expand_until(|node| {
+                !region.intersects_aabb(&node.bounding_box) || !depth.includes_children(node)
+            })
+            .await?;
+
+        Ok(visited
+            .into_iter()
+            .filter(|node_id| {
+                let node = self
+                    .octree()
+                    .node(*node_id)
+                    .expect("missing node in hierarchy, shouldn't happen");
+
+                depth.includes(node) && region.intersects_aabb(&node.bounding_box)
+            })
+            .collect())
+    }
+
+    /// Loads the points of the nodes which are inside the region, concatenated.
+    pub(crate) async fn load_points_in<R: Region>(
+        &self,
+        nodes: Vec<NodeId>,
+        region: &R,
+        selection: &AttributeSelection,
+    ) -> Result<PointBuffer, QueryError> {
+        let selection = with_positions(selection);
+
+        let mut buffers = stream::iter(nodes)
+            .map(|node_id| self.load_point_buffer_with(node_id, &selection))
+            .buffered(DEFAULT_MAX_CONCURRENT_REQUESTS);
+
+        let mut points = PointBuffer::default();
+        while let Some(buffer) = buffers.next().await {
+            let buffer = buffer?;
+            // empty nodes don't need to be merged
+            if buffer.num_points == 0 {
+                continue;
+            }
+            points.append(buffer.filter(|i| region.contains_point(buffer.positions[i])))?;
+        }
+
+        Ok(points)
+    }
+}
+
+// The selection, including positions
+pub(crate) fn with_positions(selection: &AttributeSelection) -> AttributeSelection {
+    match selection {
+        AttributeSelection::Only(names)
+            if !names.iter().any(|name| is_position_attribute(name)) =>
+        {
+            let mut names = names.clone();
+            names.push("position".to_string());
+            AttributeSelection::Only(names)
+        }
+        selection => selection.clone(),
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 use async_trait::async_trait;
-use super::{ResourceClient, ResourceError};
+use super::{ResourceClient, ResourceError, ResourceInfo};
 #[cfg(target_arch = "wasm32")]
 use ehttp::Mode;
 
@@ -46,4 +46,35 @@ impl ResourceClient for EhttpClient {
 
         Ok(response.bytes)
     }
+
+    async fn head(&self, url: &str) -> Result<ResourceInfo, ResourceError> {
+        let (tx, rx) = futures::channel::oneshot::channel();
+
+        let request = ehttp::Request {
+            method: "HEAD".to_owned(),
+            url: url.to_string(),
+            body: vec![],
+            headers: Default::default(),
+            #[cfg(target_arch = "wasm32")]
+            mode: Mode::default(),
+        };
+
+        ehttp::fetch(request, move |res| {
+            let _ = tx.send(res);
+        });
+
+        let response = rx.await.map_err(|_| ResourceError::Network("channel closed".to_string()))?;
+        let response = response.map_err(|e| ResourceError::Network(format!("{:?}", e)))?;
+        if !response.ok {
+            return Err(ResourceError::Status(response.status));
+        }
+
+        Ok(ResourceInfo {
+            size: response
+                .headers
+                .get("content-length")
+                .and_then(|size| size.parse().ok()),
+            etag: response.headers.get("etag").map(|etag| etag.to_string()),
+        })
+    }
 }
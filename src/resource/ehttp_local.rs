@@ -1,5 +1,5 @@
 use crate::resource::ehttp::EhttpClient;
-use crate::resource::{ResourceClient, ResourceError};
+use crate::resource::{ResourceClient, ResourceError, ResourceInfo};
 use async_trait::async_trait;
 use futures::channel::mpsc::{SendError, UnboundedReceiver, UnboundedSender, unbounded};
 use futures::channel::oneshot;
@@ -28,15 +28,18 @@ enum RequestPayload {
         length: usize,
         headers: Option<BTreeMap<String, String>>,
     },
+    Head {
+        url: String,
+    },
 }
 
 struct ResponseMessage {
-    payload: Result<Vec<u8>, ResourceError>,
+    payload: Result<ResponsePayload, ResourceError>,
 }
 
 enum ResponsePayload {
-    Success(Vec<u8>),
-    Error(ResourceError),
+    Bytes(Vec<u8>),
+    Info(ResourceInfo),
 }
 
 impl EhttpClientLocal {
@@ -52,7 +55,10 @@ impl EhttpClientLocal {
         Self { tx_request }
     }
 
-    async fn send_request(&self, payload: RequestPayload) -> Result<Vec<u8>, ResourceError> {
+    async fn send_request(
+        &self,
+        payload: RequestPayload,
+    ) -> Result<ResponsePayload, ResourceError> {
         let (tx_response, rx_response) = oneshot::channel();
 
         let request_message = RequestMessage {
@@ -88,7 +94,8 @@ impl ResourceClient for EhttpClientLocal {
             url: url.to_string(),
             headers,
         })
-        .await
+        .await?
+        .into_bytes()
     }
 
     async fn get_range(
@@ -104,7 +111,33 @@ impl ResourceClient for EhttpClientLocal {
             length,
             headers,
         })
-        .await
+        .await?
+        .into_bytes()
+    }
+
+    async fn head(&self, url: &str) -> Result<ResourceInfo, ResourceError> {
+        match self
+            .send_request(RequestPayload::Head {
+                url: url.to_string(),
+            })
+            .await?
+        {
+            ResponsePayload::Info(info) => Ok(info),
+            ResponsePayload::Bytes(_) => Err(ResourceError::Other(
+                "Unexpected response to a HEAD request".to_string(),
+            )),
+        }
+    }
+}
+
+impl ResponsePayload {
+    fn into_bytes(self) -> Result<Vec<u8>, ResourceError> {
+        match self {
+            ResponsePayload::Bytes(bytes) => Ok(bytes),
+            ResponsePayload::Info(_) => Err(ResourceError::Other(
+                "Unexpected response to a GET request".to_string(),
+            )),
+        }
     }
 }
 
@@ -116,7 +149,10 @@ async fn process_requests(
     while let Some(message) = rx_requests.next().await {
         match message.payload {
             RequestPayload::Get { url, headers } => {
-                let response = ehttp_client.get(&url, headers).await;
+                let response = ehttp_client
+                    .get(&url, headers)
+                    .await
+                    .map(ResponsePayload::Bytes);
                 let _ = message
                     .tx_response
                     .send(ResponseMessage { payload: response });
@@ -127,7 +163,16 @@ async fn process_requests(
                 length,
                 headers,
             } => {
-                let response = ehttp_client.get_range(&url, offset, length, headers).await;
+                let response = ehttp_client
+                    .get_range(&url, offset, length, headers)
+                    .await
+                    .map(ResponsePayload::Bytes);
+                let _ = message
+                    .tx_response
+                    .send(ResponseMessage { payload: response });
+            }
+            RequestPayload::Head { url } => {
+                let response = ehttp_client.head(&url).await.map(ResponsePayload::Info);
                 let _ = message
                     .tx_response
                     .send(ResponseMessage { payload: response });
@@ -1,7 +1,8 @@
-use super::{ResourceClient, ResourceError};
+use super::{ResourceClient, ResourceError, ResourceInfo};
 use async_trait::async_trait;
 use std::collections::BTreeMap;
 use std::io::SeekFrom;
+use std::time::UNIX_EPOCH;
 #[cfg(all(not(feature = "tokio"), not(feature = "async-fs")))]
 use std::io::{Read, Seek};
 
@@ -77,4 +78,34 @@ impl ResourceClient for FileClient {
             Ok(bytes)
         }
     }
+
+    async fn head(&self, url: &str) -> Result<ResourceInfo, ResourceError> {
+        let path = {
+            if url.starts_with("file://") {
+                url.strip_prefix("file://").unwrap()
+            } else {
+                url
+            }
+        };
+        #[cfg(feature = "tokio")]
+        let metadata = tokio::fs::metadata(path).await?;
+
+        #[cfg(all(feature = "async-fs", not(feature = "tokio")))]
+        let metadata = async_fs::metadata(path).await?;
+
+        #[cfg(all(not(feature = "tokio"), not(feature = "async-fs")))]
+        let metadata = std::fs::metadata(path)?;
+
+        // use the modification time as a version
+        let etag = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| modified.as_nanos().to_string());
+
+        Ok(ResourceInfo {
+            size: Some(metadata.len()),
+            etag,
+        })
+    }
 }
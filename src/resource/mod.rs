@@ -23,7 +23,7 @@ pub struct ResourceLoader {
     file: file::FileClient,
 
     #[cfg(all(feature = "reqwest", not(all(feature = "wasm", feature = "ehttp"))))]
-    http: reqwest::Client,
+    http: reqwest::ReqwestClient,
 
     #[cfg(all(feature = "ehttp", not(feature = "ehttp_local")))]
     http: ehttp::EhttpClient,
@@ -38,7 +38,7 @@ impl ResourceLoader {
             #[cfg(feature = "fs")]
             file: file::FileClient,
             #[cfg(all(feature = "reqwest", not(all(feature = "wasm", feature = "ehttp"))))]
-            http: reqwest::Client::new(),
+            http: reqwest::ReqwestClient::new(),
 
             #[cfg(all(feature = "ehttp", not(feature = "ehttp_local")))]
             http: ehttp::EhttpClient,
@@ -89,7 +89,7 @@ impl ResourceLoader {
         url: &str,
         headers: Option<BTreeMap<String, String>>,
     ) -> Result<Vec<u8>, ResourceError> {
-        Ok(self.get_delegate(url)?.get(url, headers).await?)
+        self.get_delegate(url)?.get(url, headers).await
     }
 
     pub async fn get_range(
@@ -99,10 +99,9 @@ impl ResourceLoader {
         length: usize,
         headers: Option<BTreeMap<String, String>>,
     ) -> Result<Vec<u8>, ResourceError> {
-        Ok(self
-            .get_delegate(url)?
+        self.get_delegate(url)?
             .get_range(url, offset, length, headers)
-            .await?)
+            .await
     }
 
     pub async fn get_json<T: DeserializeOwned + Send>(
@@ -110,7 +109,26 @@ impl ResourceLoader {
         url: &str,
         headers: Option<BTreeMap<String, String>>,
     ) -> Result<T, ResourceError> {
-        Ok(self.get_delegate(url)?.get_json(url, headers).await?)
+        self.get_delegate(url)?.get_json(url, headers).await
+    }
+
+    /// Returns the size and the version of a resource, without downloading it if the client allows it.
+    pub async fn head(&self, url: &str) -> Result<ResourceInfo, ResourceError> {
+        self.get_delegate(url)?.head(url).await
+    }
+}
+
+/// Size and version of a resource, used to detect that it has changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceInfo {
+    pub size: Option<u64>,
+    /// `ETag` of http resources, modification time of files.
+    pub etag: Option<String>,
+}
+
+impl Default for ResourceLoader {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -132,8 +150,8 @@ trait ResourceClient: Send + Sync + Clone {
         // Compute the Range header
         let end = offset
             .checked_add(length as u64)
-            .map(|v| v - 1)
-            .ok_or_else(|| ResourceError::Other("Range overflow".into()))?;
+            .and_then(|v| v.checked_sub(1))
+            .ok_or_else(|| ResourceError::Other("Invalid range".into()))?;
         let range_value = format!("bytes={}-{}", offset, end);
 
         // Merge headers
@@ -152,6 +170,16 @@ trait ResourceClient: Send + Sync + Clone {
         let bytes = self.get(url, headers).await?;
         Ok(serde_json::from_slice(&bytes)?)
     }
+
+    /// Default implementation downloads the whole resource to get its size.
+    async fn head(&self, url: &str) -> Result<ResourceInfo, ResourceError> {
+        let bytes = self.get(url, None).await?;
+
+        Ok(ResourceInfo {
+            size: Some(bytes.len() as u64),
+            etag: None,
+        })
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -159,7 +187,7 @@ pub enum ErasedResourceClient<'a> {
     #[cfg(feature = "fs")]
     File(&'a file::FileClient),
     #[cfg(all(feature = "reqwest", not(all(feature = "wasm", feature = "ehttp"))))]
-    Http(&'a http::HttpClient),
+    Http(&'a reqwest::ReqwestClient),
     #[cfg(all(feature = "ehttp", not(feature = "ehttp_local")))]
     Http(&'a ehttp::EhttpClient),
     #[cfg(feature = "ehttp_local")]
@@ -225,6 +253,19 @@ impl ResourceClient for ErasedResourceClient<'_> {
             )),
         }
     }
+
+    #[allow(unused_variables)]
+    async fn head(&self, url: &str) -> Result<ResourceInfo, ResourceError> {
+        match self {
+            #[cfg(feature = "fs")]
+            ErasedResourceClient::File(delegate) => delegate.head(url).await,
+            #[cfg(any(feature = "reqwest", feature = "ehttp", feature = "ehttp_local"))]
+            ErasedResourceClient::Http(delegate) => delegate.head(url).await,
+            _ => Err(ResourceError::Unsupported(
+                "Scheme not supported".to_string(),
+            )),
+        }
+    }
 }
 
 #[async_trait]
@@ -254,6 +295,10 @@ impl<C: ResourceClient> ResourceClient for Arc<C> {
     ) -> Result<T, ResourceError> {
         (**self).get_json(url, headers).await
     }
+
+    async fn head(&self, url: &str) -> Result<ResourceInfo, ResourceError> {
+        (**self).head(url).await
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -1,7 +1,8 @@
-use super::{ResourceClient, ResourceError};
+use super::{ResourceClient, ResourceError, ResourceInfo};
 use async_trait::async_trait;
 use std::collections::BTreeMap;
 
+#[derive(Clone, Debug, Default)]
 pub struct ReqwestClient {
     client: reqwest::Client,
 }
@@ -32,4 +33,70 @@ impl ResourceClient for ReqwestClient {
         }
         Ok(resp.bytes().await.map_err(|e| ResourceError::Network(e.to_string()))?.to_vec())
     }
+
+    async fn head(&self, url: &str) -> Result<ResourceInfo, ResourceError> {
+        let resp = self.client.head(url).send().await.map_err(|e| ResourceError::Network(e.to_string()))?;
+        let status = resp.status().as_u16();
+        if !(200..300).contains(&status) {
+            return Err(ResourceError::Status(status));
+        }
+
+        let header = |name: &str| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        // `Response::content_length` is the length of the (empty) body of a HEAD response
+        Ok(ResourceInfo {
+            size: header("content-length").and_then(|size| size.parse().ok()),
+            etag: header("etag"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    // Answers one request with the headers of a 1000 bytes resource, returns its request line
+    fn serve_once(listener: TcpListener) -> std::thread::JoinHandle<String> {
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            let mut stream = reader.into_inner();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1000\r\netag: \"v1\"\r\n\r\n")
+                .unwrap();
+            request_line
+        })
+    }
+
+    #[tokio::test]
+    async fn head_reads_size_and_etag() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hierarchy.bin", listener.local_addr().unwrap());
+        let server = serve_once(listener);
+
+        let info = ReqwestClient::new().head(&url).await.unwrap();
+
+        assert!(server.join().unwrap().starts_with("HEAD /hierarchy.bin "));
+        assert_eq!(
+            info,
+            ResourceInfo {
+                size: Some(1000),
+                etag: Some("\"v1\"".to_string()),
+            }
+        );
+    }
 }
@@ -0,0 +1,483 @@
+//! Writing Potree 2.0 datasets (`metadata.json`, `hierarchy.bin` and `octree.bin`).
+
+use crate::encoder::encode_points;
+use crate::hierarchy::HierarchyNodeEntry;
+use crate::metadata::{AttributeMetadata, AttributeType, Metadata};
+use crate::octree::key::OctreeKey;
+use crate::point::buffer::{PointBuffer, is_position_attribute};
+use binrw::BinWriterExt;
+use glam::DVec3;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Cursor;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum WriteDatasetError {
+    #[error("Invalid node name: {0}")]
+    InvalidNodeName(String),
+
+    #[error("Encoding {0} is not supported by the writer")]
+    EncodingUnsupported(String),
+
+    #[error("Invalid hierarchy step size {0}")]
+    InvalidStepSize(u16),
+
+    #[error("Missing attribute {attribute} in the points of node {node}")]
+    MissingAttribute { node: String, attribute: String },
+
+    #[error(
+        "Invalid length of attribute {attribute} in node {node}: expected {expected} values, found {actual}"
+    )]
+    InvalidAttributeLength {
+        node: String,
+        attribute: String,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("Position {position} of node {node} can't be stored with the scale and offset")]
+    PositionOutOfRange { node: String, position: DVec3 },
+
+    #[error("Error compressing node {node}: {source}")]
+    Compression {
+        node: String,
+        source: std::io::Error,
+    },
+
+    #[error("Error writing hierarchy: {0}")]
+    InvalidBinaryData(#[from] binrw::Error),
+
+    #[error("Error serializing metadata: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Error writing dataset: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Collects the points of the nodes of an octree, then encodes them as a Potree 2.0 dataset.
+///
+/// The metadata describes the dataset to write: its bounding box, spacing, scale and offset,
+/// attributes, encoding (`DEFAULT` or `BROTLI`) and hierarchy step size. The number of points,
+/// the hierarchy size and depth and the attribute ranges are filled in by the writer.
+#[derive(Clone, Debug)]
+pub struct PotreeWriter {
+    metadata: Metadata,
+    nodes: BTreeMap<String, PointBuffer>,
+}
+
+/// An encoded Potree 2.0 dataset.
+#[derive(Clone, Debug)]
+pub struct PotreeDataset {
+    pub metadata: Metadata,
+    /// Content of `hierarchy.bin`
+    pub hierarchy: Vec<u8>,
+    /// Content of `octree.bin`
+    pub octree: Vec<u8>,
+}
+
+/// Where the points of a node are written in `octree.bin`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WrittenNode {
+    pub num_points: u32,
+    pub byte_offset: u64,
+    pub byte_size: u64,
+}
+
+/// Minimum and maximum of each element of the attributes, accumulated over the points.
+#[derive(Clone, Debug)]
+pub(crate) struct AttributeRanges {
+    min: Vec<Vec<f64>>,
+    max: Vec<Vec<f64>>,
+}
+
+impl PotreeWriter {
+    pub fn new(metadata: Metadata) -> Self {
+        Self {
+            metadata,
+            nodes: BTreeMap::new(),
+        }
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Sets the points of the node named `name`, e.g. `r0413`.
+    ///
+    /// Nodes without points may be omitted, the ancestors of the nodes are written anyway.
+    pub fn add_node(&mut self, name: &str, points: PointBuffer) -> Result<(), WriteDatasetError> {
+        if OctreeKey::from_name(name).is_none() {
+            return Err(WriteDatasetError::InvalidNodeName(name.to_string()));
+        }
+
+        self.nodes.insert(name.to_string(), points);
+        Ok(())
+    }
+
+    /// Encodes the nodes and their hierarchy.
+    ///
+    /// The hierarchy is split in chunks of `step_size` levels. The nodes ending a chunk which
+    /// have children are written as proxies, pointing to the chunk holding their subtree.
+    pub fn write(&self) -> Result<PotreeDataset, WriteDatasetError> {
+        // every ancestor of a node is a node, possibly without points
+        let mut names: BTreeMap<&str, Option<&PointBuffer>> = BTreeMap::from([("r", None)]);
+        for (name, points) in &self.nodes {
+            for end in 1..name.len() {
+                names.entry(&name[..end]).or_default();
+            }
+            names.insert(name, Some(points));
+        }
+
+        // points, breadth first
+        let mut octree = Vec::new();
+        let mut written: BTreeMap<String, WrittenNode> = BTreeMap::new();
+        let mut ranges = AttributeRanges::new(&self.metadata.attributes);
+
+        for name in breadth_first(|name| names.contains_key(name)) {
+            let mut node = WrittenNode::default();
+
+            if let Some(points) = names[name.as_str()].filter(|points| points.num_points > 0) {
+                let bytes = encode_points(&self.metadata, &name, points)?;
+                ranges.add(&self.metadata.attributes, points);
+
+                node.num_points = points.num_points as u32;
+                node.byte_offset = octree.len() as u64;
+                node.byte_size = bytes.len() as u64;
+                octree.extend(bytes);
+            }
+
+            written.insert(name, node);
+        }
+
+        let (hierarchy, first_chunk_size) =
+            encode_hierarchy(&written, self.metadata.hierarchy.step_size)?;
+
+        let mut metadata = self.metadata.clone();
+        finish_metadata(&mut metadata, &written, first_chunk_size);
+        ranges.apply(&mut metadata.attributes);
+
+        Ok(PotreeDataset {
+            metadata,
+            hierarchy,
+            octree,
+        })
+    }
+}
+
+impl AttributeRanges {
+    pub fn new(attributes: &[AttributeMetadata]) -> Self {
+        let empty = |value: f64| {
+            attributes
+                .iter()
+                .map(|attribute| vec![value; attribute.num_elements as usize])
+                .collect()
+        };
+
+        Self {
+            min: empty(f64::INFINITY),
+            max: empty(f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn add(&mut self, attributes: &[AttributeMetadata], points: &PointBuffer) {
+        for ((attribute, min), max) in attributes.iter().zip(&mut self.min).zip(&mut self.max) {
+            let mut add = |element: usize, value: f64| {
+                min[element] = min[element].min(value);
+                max[element] = max[element].max(value);
+            };
+
+            if is_position_attribute(&attribute.name) {
+                for position in &points.positions {
+                    for (element, value) in position.to_array().into_iter().enumerate() {
+                        add(element, value);
+                    }
+                }
+            } else if attribute.r#type != AttributeType::Undefined
+                && let Some(column) = points.attribute(&attribute.name)
+            {
+                for i in 0..points.num_points {
+                    for element in 0..attribute.num_elements as usize {
+                        add(element, column.get_f64(i, element).unwrap_or_default());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sets the ranges of the attributes which have values.
+    pub fn apply(self, attributes: &mut [AttributeMetadata]) {
+        for ((attribute, min), max) in attributes.iter_mut().zip(self.min).zip(self.max) {
+            // no values, or an undefined attribute
+            if min.iter().any(|value| value.is_infinite()) {
+                continue;
+            }
+
+            attribute.min = min.into_iter().map(|value| value as f32).collect();
+            attribute.max = max.into_iter().map(|value| value as f32).collect();
+        }
+    }
+}
+
+impl PotreeDataset {
+    /// The metadata, as the content of `metadata.json`.
+    pub fn metadata_json(&self) -> Result<String, WriteDatasetError> {
+        Ok(serde_json::to_string_pretty(&self.metadata)?)
+    }
+
+    /// Writes `metadata.json`, `hierarchy.bin` and `octree.bin` in the directory,
+    /// which is created if needed.
+    #[cfg(feature = "fs")]
+    pub fn write_to_dir<P: AsRef<std::path::Path>>(&self, dir: P) -> Result<(), WriteDatasetError> {
+        let dir = dir.as_ref();
+
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("metadata.json"), self.metadata_json()?)?;
+        std::fs::write(dir.join("hierarchy.bin"), &self.hierarchy)?;
+        std::fs::write(dir.join("octree.bin"), &self.octree)?;
+
+        Ok(())
+    }
+}
+
+/// Encodes the hierarchy of the nodes, which must include the ancestors of every node.
+/// Returns the content of `hierarchy.bin` and the size of its first chunk.
+///
+/// The hierarchy is split in chunks of `step_size` levels. The nodes ending a chunk which
+/// have children are written as proxies, pointing to the chunk holding their subtree.
+pub(crate) fn encode_hierarchy(
+    nodes: &BTreeMap<String, WrittenNode>,
+    step_size: u16,
+) -> Result<(Vec<u8>, u64), WriteDatasetError> {
+    if step_size == 0 {
+        return Err(WriteDatasetError::InvalidStepSize(step_size));
+    }
+
+    let contains = |name: &str| nodes.contains_key(name);
+    let child_mask = |name: &str| {
+        (0..8)
+            .filter(|index| contains(&format!("{name}{index}")))
+            .fold(0_u8, |mask, index| mask | (1 << index))
+    };
+    let is_chunk_root = |name: &str| {
+        let level = name.len() - 1;
+        name == "r" || (level.is_multiple_of(step_size as usize) && child_mask(name) != 0)
+    };
+
+    // nodes of each chunk, breadth first, chunks in the order of their roots
+    let mut chunks: Vec<(String, Vec<String>)> = Vec::new();
+    let mut chunk_roots = VecDeque::from(["r".to_string()]);
+
+    while let Some(root) = chunk_roots.pop_front() {
+        let mut chunk = Vec::new();
+        let mut queue = VecDeque::from([root.clone()]);
+
+        while let Some(name) = queue.pop_front() {
+            if name != root && is_chunk_root(&name) {
+                chunk_roots.push_back(name.clone());
+            } else {
+                queue.extend(children(&name, contains));
+            }
+            chunk.push(name);
+        }
+
+        chunks.push((root, chunk));
+    }
+
+    // chunks are written one after the other, the first one being the root's
+    const BYTES_PER_NODE: u64 = 22;
+    let mut chunk_ranges = BTreeMap::new();
+    let mut chunk_offset = 0;
+    for (root, chunk) in &chunks {
+        let chunk_size = chunk.len() as u64 * BYTES_PER_NODE;
+        chunk_ranges.insert(root.as_str(), (chunk_offset, chunk_size));
+        chunk_offset += chunk_size;
+    }
+
+    let mut hierarchy = Cursor::new(Vec::with_capacity(chunk_offset as usize));
+    for (root, chunk) in &chunks {
+        for name in chunk {
+            let node = &nodes[name];
+            let child_mask = child_mask(name);
+
+            let entry = match chunk_ranges.get(name.as_str()) {
+                Some((byte_offset, byte_size)) if name != root => HierarchyNodeEntry {
+                    r#type: 2,
+                    child_mask,
+                    num_points: node.num_points,
+                    byte_offset: *byte_offset,
+                    byte_size: *byte_size,
+                },
+                _ => HierarchyNodeEntry {
+                    r#type: if child_mask == 0 { 1 } else { 0 },
+                    child_mask,
+                    num_points: node.num_points,
+                    byte_offset: node.byte_offset,
+                    byte_size: node.byte_size,
+                },
+            };
+
+            hierarchy.write_le(&entry)?;
+        }
+    }
+
+    Ok((hierarchy.into_inner(), chunk_ranges["r"].1))
+}
+
+/// Sets the version, number of points and hierarchy of the metadata of the written nodes.
+pub(crate) fn finish_metadata(
+    metadata: &mut Metadata,
+    nodes: &BTreeMap<String, WrittenNode>,
+    first_chunk_size: u64,
+) {
+    metadata.version = "2.0".to_string();
+    metadata.points = nodes.values().map(|node| node.num_points as u64).sum();
+    metadata.hierarchy.first_chunk_size = first_chunk_size;
+    metadata.hierarchy.depth = nodes.keys().map(|name| name.len() - 1).max().unwrap_or(0) as u16;
+}
+
+/// Names of the nodes for which `contains` returns true, breadth first from the root.
+pub(crate) fn breadth_first(contains: impl Fn(&str) -> bool + Copy) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut queue = VecDeque::from(["r".to_string()]);
+
+    while let Some(name) = queue.pop_front() {
+        queue.extend(children(&name, contains));
+        names.push(name);
+    }
+
+    names
+}
+
+fn children(name: &str, contains: impl Fn(&str) -> bool) -> Vec<String> {
+    (0..8)
+        .map(|index| format!("{name}{index}"))
+        .filter(|child| contains(child))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{BoundingBox, HierarchyMetadata};
+    use crate::octree::snapshot::OctreeNodeSnapshot;
+    use crate::point_cloud::PotreePointCloud;
+    use crate::resource::ResourceLoader;
+    use binrw::BinReaderExt;
+
+    fn metadata(first_chunk_size: u64) -> Metadata {
+        Metadata {
+            version: "2.0".to_string(),
+            name: String::new(),
+            description: String::new(),
+            points: 0,
+            projection: String::new(),
+            hierarchy: HierarchyMetadata {
+                first_chunk_size,
+                step_size: 0,
+                depth: 0,
+            },
+            offset: [0.0; 3],
+            scale: [0.001; 3],
+            spacing: 1.0,
+            bounding_box: BoundingBox {
+                min: [0.0; 3],
+                max: [8.0; 3],
+            },
+            encoding: "DEFAULT".to_string(),
+            attributes: Vec::new(),
+        }
+    }
+
+    // Nodes with distinct points and byte ranges
+    fn written_nodes(names: &[&str]) -> BTreeMap<String, WrittenNode> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let node = WrittenNode {
+                    num_points: i as u32 + 1,
+                    byte_offset: i as u64 * 100,
+                    byte_size: i as u64 + 10,
+                };
+                (name.to_string(), node)
+            })
+            .collect()
+    }
+
+    // Parses the encoded hierarchy chunk by chunk, as the proxies are met
+    fn parse(hierarchy: &[u8], first_chunk_size: u64) -> PotreePointCloud {
+        let metadata = metadata(first_chunk_size);
+        let root = OctreeNodeSnapshot::from(&metadata.create_root_node());
+        let mut point_cloud =
+            PotreePointCloud::from_snapshot("", metadata, &[root], ResourceLoader::new()).unwrap();
+
+        while let Some((node_id, node)) = point_cloud
+            .octree()
+            .depth_first(point_cloud.octree().root_id())
+            .find(|(_, node)| node.node_type == 2)
+        {
+            let start = node.hierarchy_byte_offset as usize;
+            let chunk = &hierarchy[start..start + node.hierarchy_byte_size as usize];
+            point_cloud.parse_hierarchy_chunk(node_id, chunk).unwrap();
+        }
+
+        point_cloud
+    }
+
+    fn assert_parsed(point_cloud: &PotreePointCloud, nodes: &BTreeMap<String, WrittenNode>) {
+        assert_eq!(point_cloud.octree().len(), nodes.len());
+
+        for (name, written) in nodes {
+            let node_id = point_cloud.node_id_by_name(name).unwrap();
+            let node = point_cloud.octree().node(node_id).unwrap();
+            let is_leaf = !nodes
+                .keys()
+                .any(|other| other.len() == name.len() + 1 && other.starts_with(name.as_str()));
+
+            assert_eq!(node.num_points, written.num_points, "{name}");
+            assert_eq!(node.byte_offset, written.byte_offset, "{name}");
+            assert_eq!(node.byte_size, written.byte_size, "{name}");
+            assert_eq!(node.node_type, if is_leaf { 1 } else { 0 }, "{name}");
+        }
+    }
+
+    #[test]
+    fn encode_single_chunk() {
+        let nodes = written_nodes(&["r", "r0", "r03", "r036", "r7"]);
+
+        let (hierarchy, first_chunk_size) = encode_hierarchy(&nodes, 8).unwrap();
+
+        assert_eq!(first_chunk_size, 5 * 22);
+        assert_eq!(hierarchy.len(), 5 * 22);
+        assert_parsed(&parse(&hierarchy, first_chunk_size), &nodes);
+    }
+
+    #[test]
+    fn encode_chunks_with_proxies() {
+        let nodes = written_nodes(&[
+            "r", "r0", "r03", "r036", "r0361", "r03615", "r2", "r24", "r7", "r70", "r701",
+        ]);
+
+        let (hierarchy, first_chunk_size) = encode_hierarchy(&nodes, 2).unwrap();
+
+        // r, r0, r2, r7, r03, r24, r70: r03 and r70 have children and end the first chunk
+        assert_eq!(first_chunk_size, 7 * 22);
+        let mut cursor = Cursor::new(&hierarchy[..first_chunk_size as usize]);
+        let entries: Vec<HierarchyNodeEntry> = (0..7).map(|_| cursor.read_le().unwrap()).collect();
+        let types: Vec<u8> = entries.iter().map(|entry| entry.r#type).collect();
+        assert_eq!(types, [0, 0, 0, 0, 2, 1, 2]);
+
+        let point_cloud = parse(&hierarchy, first_chunk_size);
+        assert_parsed(&point_cloud, &nodes);
+    }
+
+    #[test]
+    fn encode_rejects_zero_step_size() {
+        let nodes = written_nodes(&["r"]);
+
+        assert!(matches!(
+            encode_hierarchy(&nodes, 0),
+            Err(WriteDatasetError::InvalidStepSize(0))
+        ));
+    }
+}
@@ -0,0 +1,139 @@
+#![cfg(feature = "fs")]
+
+use glam::DVec3;
+use potree::metadata::{AttributeMetadata, AttributeType};
+use potree::octree::aabb::Aabb;
+use potree::prelude::*;
+use std::path::PathBuf;
+
+const NUM_POINTS: usize = 20_000;
+const BATCH_SIZE: usize = 1_000;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("potree-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// Pseudo-random positions on the grid of the scale, half of them in a cluster
+fn positions(bounding_box: &Aabb) -> Vec<DVec3> {
+    let mut seed = 7_u64;
+    let mut random = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 11) as f64 / (1_u64 << 53) as f64
+    };
+
+    (0..NUM_POINTS)
+        .map(|i| {
+            let size = bounding_box.max - bounding_box.min;
+            let (origin, extent) = if i % 2 == 0 {
+                (bounding_box.min + size * 0.1, size * 0.05)
+            } else {
+                (bounding_box.min, size)
+            };
+            let position = origin + DVec3::new(random(), random(), random()) * extent;
+            ((position - bounding_box.min) / 0.001).round() * 0.001 + bounding_box.min
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn convert_round_trip() {
+    let dir = temp_dir("converter");
+    // chunks left by a previous conversion must not end up in the dataset
+    std::fs::create_dir_all(dir.join(".chunks")).unwrap();
+    std::fs::write(dir.join(".chunks").join("r.bin"), [0; 200]).unwrap();
+
+    let bounding_box = Aabb::new(
+        DVec3::new(100.0, 200.0, 10.0),
+        DVec3::new(180.0, 240.0, 30.0),
+    );
+    let options = ConverterOptions {
+        encoding: "DEFAULT".to_string(),
+        max_node_points: 500,
+        max_chunk_points: 4_000,
+        max_points_in_memory: 3_000,
+        step_size: 2,
+        ..Default::default()
+    };
+    let mut converter = PotreeConverter::new(
+        &dir,
+        &bounding_box,
+        vec![AttributeMetadata::new(
+            "intensity",
+            AttributeType::UInt16,
+            1,
+        )],
+        options,
+    )
+    .unwrap();
+
+    let positions = positions(&bounding_box);
+    for (batch, positions) in positions.chunks(BATCH_SIZE).enumerate() {
+        let mut points = PointBuffer::new(positions.len());
+        points.positions = positions.to_vec();
+        // the intensity identifies the point
+        let intensity = (0..positions.len())
+            .map(|i| (batch * BATCH_SIZE + i) as u16)
+            .collect();
+        points.attributes = vec![AttributeBuffer {
+            name: "intensity".to_string(),
+            num_elements: 1,
+            data: AttributeData::UInt16(intensity),
+        }];
+
+        converter.add_points(&points).unwrap();
+    }
+
+    let spilled = std::fs::read_dir(dir.join(".chunks")).unwrap().count();
+    assert!(spilled > 0);
+
+    let metadata = converter.finish().unwrap();
+    assert_eq!(metadata.points, NUM_POINTS as u64);
+    assert!(!dir.join(".chunks").exists());
+
+    let mut point_cloud = PotreePointCloud::from_url(dir.to_str().unwrap(), ResourceLoader::new())
+        .await
+        .unwrap();
+    point_cloud.load_entire_hierarchy().await.unwrap();
+
+    let node_ids: Vec<_> = point_cloud
+        .octree()
+        .breadth_first(point_cloud.octree().root_id())
+        .map(|(node_id, _)| node_id)
+        .collect();
+    let mut found = vec![false; NUM_POINTS];
+    for node_id in node_ids {
+        let points = point_cloud.load_point_buffer(node_id).await.unwrap();
+        let Some(intensity) = points.attribute("intensity") else {
+            continue;
+        };
+
+        for (i, position) in points.positions.iter().enumerate() {
+            let id = intensity.get_f64(i, 0).unwrap() as usize;
+            assert!(!found[id], "point {id} written twice");
+            assert!(position.distance(positions[id]) < 1e-6, "point {id}");
+            found[id] = true;
+        }
+    }
+    assert!(found.iter().all(|found| *found));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn converter_rejects_zero_step_size() {
+    let dir = temp_dir("converter-step-size");
+    let options = ConverterOptions {
+        step_size: 0,
+        ..Default::default()
+    };
+    let bounding_box = Aabb::new(DVec3::ZERO, DVec3::ONE);
+
+    let result = PotreeConverter::new(&dir, &bounding_box, Vec::new(), options);
+
+    assert!(matches!(result, Err(ConvertError::InvalidStepSize(0))));
+    assert!(!dir.exists());
+}
@@ -0,0 +1,115 @@
+#![cfg(feature = "fs")]
+
+use glam::{DVec2, DVec3};
+use potree::metadata::{
+    AttributeMetadata, AttributeType, BoundingBox, HierarchyMetadata, Metadata,
+};
+use potree::octree::aabb::Aabb;
+use potree::prelude::*;
+use std::path::PathBuf;
+
+const SCALE: f64 = 0.001;
+
+fn metadata() -> Metadata {
+    Metadata {
+        version: "2.0".to_string(),
+        name: "query".to_string(),
+        description: String::new(),
+        points: 0,
+        projection: String::new(),
+        hierarchy: HierarchyMetadata {
+            first_chunk_size: 0,
+            step_size: 4,
+            depth: 0,
+        },
+        offset: [0.0; 3],
+        scale: [SCALE; 3],
+        spacing: 1.0,
+        bounding_box: BoundingBox {
+            min: [0.0; 3],
+            max: [8.0; 3],
+        },
+        encoding: "DEFAULT".to_string(),
+        attributes: vec![
+            AttributeMetadata::new("position", AttributeType::Int32, 3),
+            AttributeMetadata::new("intensity", AttributeType::UInt16, 1),
+        ],
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("potree-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// A dataset holding `r` and `r01` only, so `r0` is written as an empty node
+async fn point_cloud_with_empty_node(dir: &PathBuf) -> PotreePointCloud {
+    let metadata = metadata();
+    let root = Aabb::new(
+        metadata.bounding_box.min.into(),
+        metadata.bounding_box.max.into(),
+    );
+
+    let mut writer = PotreeWriter::new(metadata);
+    for (i, name) in ["r", "r01"].into_iter().enumerate() {
+        let bounding_box = OctreeKey::from_name(name).unwrap().bounding_box(&root);
+        let center = (bounding_box.min + bounding_box.max) * 0.5;
+        let points = PointBuffer {
+            num_points: 1,
+            positions: vec![(center / SCALE).round() * SCALE],
+            attributes: vec![AttributeBuffer {
+                name: "intensity".to_string(),
+                num_elements: 1,
+                data: AttributeData::UInt16(vec![i as u16]),
+            }],
+        };
+        writer.add_node(name, points).unwrap();
+    }
+    writer.write().unwrap().write_to_dir(dir).unwrap();
+
+    let point_cloud = PotreePointCloud::from_url(dir.to_str().unwrap(), ResourceLoader::new())
+        .await
+        .unwrap();
+    let r0 = point_cloud.node_id_by_name("r0").unwrap();
+    assert_eq!(point_cloud.octree().node(r0).unwrap().num_points, 0);
+
+    point_cloud
+}
+
+#[tokio::test]
+async fn query_box_across_empty_node() {
+    let dir = temp_dir("query-box");
+    let mut point_cloud = point_cloud_with_empty_node(&dir).await;
+
+    let bounding_box = Aabb::new(DVec3::ZERO, DVec3::splat(8.0));
+    let points = point_cloud
+        .query_box(&bounding_box, QueryDepth::Full, &AttributeSelection::All)
+        .await
+        .unwrap();
+
+    assert_eq!(points.num_points, 2);
+    let intensity = points.attribute("intensity").unwrap();
+    assert_eq!(intensity.get_f64(0, 0), Some(0.0));
+    assert_eq!(intensity.get_f64(1, 0), Some(1.0));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn profile_across_empty_node() {
+    let dir = temp_dir("query-profile");
+    let mut point_cloud = point_cloud_with_empty_node(&dir).await;
+
+    // along the diagonal, through the centers of r and r01
+    let profile = Profile::new(vec![DVec2::ZERO, DVec2::splat(8.0)], 0.5);
+    let points = point_cloud
+        .profile(&profile, QueryDepth::Full, &AttributeSelection::All)
+        .await
+        .unwrap();
+
+    assert_eq!(points.len(), 2);
+    assert!(points.points.attribute("intensity").is_some());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
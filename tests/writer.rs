@@ -0,0 +1,179 @@
+#![cfg(feature = "fs")]
+
+use potree::metadata::{
+    AttributeMetadata, AttributeType, BoundingBox, HierarchyMetadata, Metadata,
+};
+use potree::octree::aabb::Aabb;
+use potree::prelude::*;
+use std::path::PathBuf;
+
+const SCALE: f64 = 0.001;
+
+fn metadata(encoding: &str) -> Metadata {
+    Metadata {
+        version: "2.0".to_string(),
+        name: "writer".to_string(),
+        description: String::new(),
+        points: 0,
+        projection: String::new(),
+        hierarchy: HierarchyMetadata {
+            first_chunk_size: 0,
+            // smaller than the depth, so some nodes are written as proxies
+            step_size: 2,
+            depth: 0,
+        },
+        offset: [0.0; 3],
+        scale: [SCALE; 3],
+        spacing: 1.0,
+        bounding_box: BoundingBox {
+            min: [0.0; 3],
+            max: [16.0; 3],
+        },
+        encoding: encoding.to_string(),
+        attributes: vec![
+            AttributeMetadata::new("position", AttributeType::Int32, 3),
+            AttributeMetadata::new("intensity", AttributeType::UInt16, 1),
+            AttributeMetadata::new("rgb", AttributeType::UInt16, 3),
+        ],
+    }
+}
+
+// Points inside each node, on the grid of the scale. `r2` is not given and written empty.
+fn nodes(metadata: &Metadata) -> Vec<(String, PointBuffer)> {
+    let root = Aabb::new(
+        metadata.bounding_box.min.into(),
+        metadata.bounding_box.max.into(),
+    );
+    let names = [
+        "r", "r0", "r03", "r036", "r0361", "r24", "r7", "r70", "r701", "r7015",
+    ];
+
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let bounding_box = OctreeKey::from_name(name).unwrap().bounding_box(&root);
+            let num_points = 4;
+            let positions = (0..num_points)
+                .map(|j| {
+                    let position = bounding_box.min
+                        + (bounding_box.max - bounding_box.min) * (0.1 + 0.2 * j as f64);
+                    (position / SCALE).round() * SCALE
+                })
+                .collect();
+            let intensity = (0..num_points).map(|j| (i * 100 + j) as u16).collect();
+            let rgb = (0..num_points * 3).map(|j| (i * 1000 + j) as u16).collect();
+
+            let points = PointBuffer {
+                num_points,
+                positions,
+                attributes: vec![
+                    AttributeBuffer {
+                        name: "intensity".to_string(),
+                        num_elements: 1,
+                        data: AttributeData::UInt16(intensity),
+                    },
+                    AttributeBuffer {
+                        name: "rgb".to_string(),
+                        num_elements: 3,
+                        data: AttributeData::UInt16(rgb),
+                    },
+                ],
+            };
+
+            (name.to_string(), points)
+        })
+        .collect()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("potree-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+async fn round_trip(encoding: &str) {
+    let metadata = metadata(encoding);
+    let nodes = nodes(&metadata);
+
+    let mut writer = PotreeWriter::new(metadata);
+    for (name, points) in &nodes {
+        writer.add_node(name, points.clone()).unwrap();
+    }
+    let dataset = writer.write().unwrap();
+    assert_eq!(dataset.metadata.points, 40);
+    assert_eq!(dataset.metadata.hierarchy.depth, 4);
+
+    let dir = temp_dir(&format!("writer-{encoding}"));
+    dataset.write_to_dir(&dir).unwrap();
+
+    let mut point_cloud = PotreePointCloud::from_url(dir.to_str().unwrap(), ResourceLoader::new())
+        .await
+        .unwrap();
+
+    // the subtrees of r03 and r70 are in other hierarchy chunks
+    for proxy in ["r03", "r70"] {
+        let node_id = point_cloud.node_id_by_name(proxy).unwrap();
+        assert_eq!(point_cloud.octree().node(node_id).unwrap().node_type, 2);
+    }
+    assert!(point_cloud.node_id_by_name("r036").is_none());
+
+    point_cloud.load_entire_hierarchy().await.unwrap();
+    // every given node and the missing r2
+    assert_eq!(point_cloud.octree().len(), nodes.len() + 1);
+
+    let r2 = point_cloud.node_id_by_name("r2").unwrap();
+    assert_eq!(point_cloud.octree().node(r2).unwrap().num_points, 0);
+
+    // empty nodes have the columns of the other nodes
+    let empty = point_cloud.load_point_buffer(r2).await.unwrap();
+    assert_eq!(empty.num_points, 0);
+    for attribute in &nodes[0].1.attributes {
+        let column = empty.attribute(&attribute.name).unwrap();
+        assert!(column.data.is_empty(), "{}", attribute.name);
+        assert_eq!(
+            column.data.r#type(),
+            attribute.data.r#type(),
+            "{}",
+            attribute.name
+        );
+    }
+
+    for (name, points) in &nodes {
+        let node_id = point_cloud.node_id_by_name(name).unwrap();
+        let loaded = point_cloud.load_point_buffer(node_id).await.unwrap();
+
+        assert_eq!(loaded.num_points, points.num_points, "{name}");
+        for (position, expected) in loaded.positions.iter().zip(&points.positions) {
+            assert!(
+                position.distance(*expected) < 1e-6,
+                "{name}: {position} {expected}"
+            );
+        }
+        for attribute in &points.attributes {
+            let loaded = loaded.attribute(&attribute.name).unwrap();
+            for i in 0..points.num_points {
+                for element in 0..attribute.num_elements as usize {
+                    assert_eq!(
+                        loaded.get_f64(i, element),
+                        attribute.get_f64(i, element),
+                        "{name} {}",
+                        attribute.name
+                    );
+                }
+            }
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn write_default_round_trip() {
+    round_trip("DEFAULT").await;
+}
+
+#[tokio::test]
+async fn write_brotli_round_trip() {
+    round_trip("BROTLI").await;
+}